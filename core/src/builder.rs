@@ -1,11 +1,13 @@
-use crate::helpers::BoundariesChecker;
+use crate::helpers::{AdjacencyPolicy, BoundariesChecker};
 use crate::rule::{
-    Rule, Rule1, Rule2, Rule3, Rule4, Rule5, Rule6, RuleProductionArg, RuleResult, TerminalRule,
+    Rule, Rule1, Rule2, Rule3, Rule4, Rule5, Rule6, RuleProductionArg, RuleResult, SequenceRule,
+    TerminalRule,
 };
 use crate::{
-    cell, pattern, CoreResult, NodePayload, Pattern, RuleSet, StashIndexable, Sym, SymbolTable,
-    TerminalPattern,
+    cell, pattern, CoreResult, NodePayload, ParsingConfig, Pattern, RuleSet, StashIndexable, Sym,
+    SymbolTable, TerminalPattern,
 };
+use std::collections::HashMap;
 
 pub struct RuleSetBuilder<StashValue: NodePayload + StashIndexable> {
     symbols: cell::RefCell<SymbolTable>,
@@ -13,6 +15,10 @@ pub struct RuleSetBuilder<StashValue: NodePayload + StashIndexable> {
     terminal_rules: cell::RefCell<Vec<Box<dyn TerminalRule<StashValue>>>>,
     word_boundaries: BoundariesChecker,
     match_boundaries: BoundariesChecker,
+    adjacency: cell::RefCell<AdjacencyPolicy>,
+    text_scans: cell::RefCell<Vec<String>>,
+    text_scan_ids: cell::RefCell<HashMap<String, usize>>,
+    text_cache_ids: cell::Cell<usize>,
 }
 
 impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
@@ -26,6 +32,10 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
             terminal_rules: cell::RefCell::new(vec![]),
             word_boundaries,
             match_boundaries,
+            adjacency: cell::RefCell::new(AdjacencyPolicy::default()),
+            text_scans: cell::RefCell::new(vec![]),
+            text_scan_ids: cell::RefCell::new(HashMap::new()),
+            text_cache_ids: cell::Cell::new(0),
         }
     }
 }
@@ -38,6 +48,45 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         self.symbols.borrow_mut().sym(val)
     }
 
+    /// Sets the adjacency policy of the rules added from now on.
+    pub fn set_adjacency(&self, adjacency: AdjacencyPolicy) {
+        *self.adjacency.borrow_mut() = adjacency;
+    }
+
+    /// Adds the rules of `rules` with the `adjacency` policy, then restores
+    /// the previous policy.
+    pub fn with_adjacency<F: FnOnce(&Self)>(&self, adjacency: AdjacencyPolicy, rules: F) {
+        let previous = self.adjacency.replace(adjacency);
+        rules(self);
+        *self.adjacency.borrow_mut() = previous;
+    }
+
+    fn adjacency(&self) -> AdjacencyPolicy {
+        self.adjacency.borrow().clone()
+    }
+
+    /// Cache id of a gazetteer or fuzzy pattern, kept apart from the text
+    /// scan which has no regex for it.
+    fn text_cache_id(&self) -> usize {
+        let id = self.text_cache_ids.get();
+        self.text_cache_ids.set(id + 1);
+        id
+    }
+
+    /// Id of the text pattern `key` in the text scan of the rule set, which
+    /// pre-filters on `regex`. Patterns with the same key share their matches.
+    fn text_scan_id(&self, key: String, regex: &str) -> usize {
+        let mut text_scans = self.text_scans.borrow_mut();
+        *self
+            .text_scan_ids
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| {
+                text_scans.push(regex.to_string());
+                text_scans.len() - 1
+            })
+    }
+
     pub fn rule_1<S, PA, V, F>(&self, sym: S, pa: PA, production: F)
     where
         S: Into<String> + AsRef<str>,
@@ -47,9 +96,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PA: Pattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule1::new(sym, pa, production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule1::new(sym, pa, production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_1_terminal<S, PA, V, F>(&self, sym: S, pa: PA, production: F)
@@ -61,9 +110,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PA: TerminalPattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.terminal_rules
-            .borrow_mut()
-            .push(Box::new(Rule1::new(sym, pa, production)))
+        self.terminal_rules.borrow_mut().push(Box::new(
+            Rule1::new(sym, pa, production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_2<S, PA, PB, V, F>(&self, sym: S, pa: PA, pb: PB, production: F)
@@ -82,9 +131,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PB: Pattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule2::new(sym, (pa, pb), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule2::new(sym, (pa, pb), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_2_terminal<S, PA, PB, V, F>(&self, sym: S, pa: PA, pb: PB, production: F)
@@ -103,9 +152,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PB: TerminalPattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.terminal_rules
-            .borrow_mut()
-            .push(Box::new(Rule2::new(sym, (pa, pb), production)))
+        self.terminal_rules.borrow_mut().push(Box::new(
+            Rule2::new(sym, (pa, pb), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_3<S, PA, PB, PC, V, F>(&self, sym: S, pa: PA, pb: PB, pc: PC, production: F)
@@ -126,9 +175,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PC: Pattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule3::new(sym, (pa, pb, pc), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule3::new(sym, (pa, pb, pc), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_3_terminal<S, PA, PB, PC, V, F>(
@@ -155,9 +204,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PC: TerminalPattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule3::new(sym, (pa, pb, pc), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule3::new(sym, (pa, pb, pc), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_4<S, PA, PB, PC, PD, V, F>(
@@ -187,9 +236,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PD: Pattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule4::new(sym, (pa, pb, pc, pd), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule4::new(sym, (pa, pb, pc, pd), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_4_terminal<S, PA, PB, PC, PD, V, F>(
@@ -219,9 +268,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PD: TerminalPattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule4::new(sym, (pa, pb, pc, pd), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule4::new(sym, (pa, pb, pc, pd), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_5<S, PA, PB, PC, PD, PE, V, F>(
@@ -254,9 +303,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PE: Pattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule5::new(sym, (pa, pb, pc, pd, pe), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule5::new(sym, (pa, pb, pc, pd, pe), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_5_terminal<S, PA, PB, PC, PD, PE, V, F>(
@@ -289,9 +338,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PE: TerminalPattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule5::new(sym, (pa, pb, pc, pd, pe), production)))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule5::new(sym, (pa, pb, pc, pd, pe), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_6<S, PA, PB, PC, PD, PE, PF, V, F>(
@@ -327,13 +376,9 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PF: Pattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule6::new(
-                sym,
-                (pa, pb, pc, pd, pe, pf),
-                production,
-            )))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule6::new(sym, (pa, pb, pc, pd, pe, pf), production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn rule_6_terminal<S, PA, PB, PC, PD, PE, PF, V, F>(
@@ -369,21 +414,78 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         PF: TerminalPattern<StashValue> + 'static,
     {
         let sym = self.sym(sym);
-        self.composition_rules
-            .borrow_mut()
-            .push(Box::new(Rule6::new(
-                sym,
-                (pa, pb, pc, pd, pe, pf),
-                production,
-            )))
+        self.composition_rules.borrow_mut().push(Box::new(
+            Rule6::new(sym, (pa, pb, pc, pd, pe, pf), production).with_adjacency(self.adjacency()),
+        ))
+    }
+
+    /// Rule made of any number of patterns, see `IntoAnyPattern` to build
+    /// `patterns`.
+    pub fn rule_seq<S, V, F>(
+        &self,
+        sym: S,
+        patterns: Vec<pattern::BoxedPattern<StashValue>>,
+        production: F,
+    ) where
+        S: Into<String> + AsRef<str>,
+        V: NodePayload<Payload = StashValue::Payload> + 'static,
+        StashValue: StashIndexable + From<V> + 'static,
+        F: for<'a> Fn(&[RuleProductionArg<'a, pattern::AnyMatch<StashValue>>]) -> RuleResult<V>
+            + 'static
+            + Send
+            + Sync,
+    {
+        let sym = self.sym(sym);
+        self.composition_rules.borrow_mut().push(Box::new(
+            SequenceRule::new(sym, patterns, production).with_adjacency(self.adjacency()),
+        ))
     }
 
     pub fn reg(&self, regex: &str) -> CoreResult<pattern::TextPattern<StashValue>> {
-        Ok(pattern::TextPattern::new(
+        let text_pattern = pattern::TextPattern::new(
             ::regex::Regex::new(regex)?,
             self.sym(regex),
             self.word_boundaries.clone(),
-        ))
+        );
+        Ok(text_pattern.with_scan_id(self.text_scan_id(format!("reg:{}", regex), regex)))
+    }
+
+    /// Pattern matching any of `entries`, named `name`. The index of the
+    /// matched entry is given by `RuleProductionArg::entry`.
+    pub fn gazetteer<I, E>(
+        &self,
+        name: &str,
+        entries: I,
+    ) -> CoreResult<pattern::GazetteerPattern<StashValue>>
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<str>,
+    {
+        Ok(
+            pattern::GazetteerPattern::new(entries, self.sym(name), self.word_boundaries.clone())?
+                .with_cache_id(self.text_cache_id()),
+        )
+    }
+
+    /// Pattern matching the words within `max_distance` edits of one of
+    /// `entries`, named `name`.
+    pub fn fuzzy<I, E>(
+        &self,
+        name: &str,
+        entries: I,
+        max_distance: usize,
+    ) -> pattern::FuzzyPattern<StashValue>
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<str>,
+    {
+        pattern::FuzzyPattern::new(
+            entries,
+            max_distance,
+            self.sym(name),
+            self.word_boundaries.clone(),
+        )
+        .with_cache_id(self.text_cache_id())
     }
 
     pub fn reg_neg_lh(
@@ -391,20 +493,103 @@ impl<StashValue: NodePayload + StashIndexable> RuleSetBuilder<StashValue> {
         regex: &str,
         neg_lh: &str,
     ) -> CoreResult<pattern::TextNegLHPattern<StashValue>> {
-        Ok(pattern::TextNegLHPattern::new(
+        let text_pattern = pattern::TextNegLHPattern::new(
             ::regex::Regex::new(regex)?,
             ::regex::Regex::new(neg_lh)?,
             self.sym(format!("{}(?:{})", regex, neg_lh)),
             self.word_boundaries.clone(),
-        ))
+        );
+        let key = format!("reg_neg_lh:{}:{}", regex, neg_lh);
+        Ok(text_pattern.with_scan_id(self.text_scan_id(key, regex)))
+    }
+
+    /// Pattern matching `regex` only when followed by `pos_lh`.
+    pub fn reg_pos_lh(
+        &self,
+        regex: &str,
+        pos_lh: &str,
+    ) -> CoreResult<pattern::TextLookaroundPattern<StashValue>> {
+        self.reg_lookaround(
+            regex,
+            pos_lh,
+            pattern::LookaroundKind::PositiveLookAhead,
+            format!("{}(?={})", regex, pos_lh),
+        )
+    }
+
+    /// Pattern matching `regex` only when not preceded by `neg_lb`.
+    pub fn reg_neg_lb(
+        &self,
+        regex: &str,
+        neg_lb: &str,
+    ) -> CoreResult<pattern::TextLookaroundPattern<StashValue>> {
+        self.reg_lookaround(
+            regex,
+            neg_lb,
+            pattern::LookaroundKind::NegativeLookBehind,
+            format!("(?<!{}){}", neg_lb, regex),
+        )
+    }
+
+    /// Pattern matching `regex` only when preceded by `pos_lb`.
+    pub fn reg_pos_lb(
+        &self,
+        regex: &str,
+        pos_lb: &str,
+    ) -> CoreResult<pattern::TextLookaroundPattern<StashValue>> {
+        self.reg_lookaround(
+            regex,
+            pos_lb,
+            pattern::LookaroundKind::PositiveLookBehind,
+            format!("(?<={}){}", pos_lb, regex),
+        )
+    }
+
+    fn reg_lookaround(
+        &self,
+        regex: &str,
+        lookaround: &str,
+        kind: pattern::LookaroundKind,
+        name: String,
+    ) -> CoreResult<pattern::TextLookaroundPattern<StashValue>> {
+        let text_pattern = pattern::TextLookaroundPattern::new(
+            ::regex::Regex::new(regex)?,
+            vec![pattern::Lookaround::new(lookaround, kind)?],
+            self.sym(name.as_str()),
+            self.word_boundaries.clone(),
+        );
+        let key = format!("reg_lookaround:{}", name);
+        Ok(text_pattern.with_scan_id(self.text_scan_id(key, regex)))
     }
 
     pub fn build(self) -> RuleSet<StashValue> {
+        let composition_rules = self.composition_rules.into_inner();
+        let mut rules_by_index = HashMap::new();
+        let mut rules_for_any_index = vec![];
+        for (idx, rule) in composition_rules.iter().enumerate() {
+            match rule.stash_indexes() {
+                Some(indexes) => {
+                    for index in indexes {
+                        rules_by_index.entry(index).or_insert(vec![]).push(idx);
+                    }
+                }
+                None => rules_for_any_index.push(idx),
+            }
+        }
+        for rules in rules_by_index.values_mut() {
+            rules.dedup();
+        }
         RuleSet {
             symbols: self.symbols.into_inner(),
             terminal_rules: self.terminal_rules.into_inner(),
-            composition_rules: self.composition_rules.into_inner(),
+            composition_rules,
             match_boundaries: self.match_boundaries,
+            config: ParsingConfig::default(),
+            // a too large set only disables the pre-filtering
+            text_scan: ::regex::RegexSet::new(self.text_scans.into_inner()).ok(),
+            rules_by_index,
+            rules_for_any_index,
+            tokenizer: None,
         }
     }
 }
@@ -1,15 +1,68 @@
 use crate::range::Range;
+use crate::tokenizer::Tokens;
+use smallvec::SmallVec;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+use unicode_script::{Script, UnicodeScript};
+use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Char class function of a custom `BoundariesClass`.
+#[derive(Clone)]
+struct CharClass(Arc<dyn Fn(char) -> char + Send + Sync>);
+
+impl fmt::Debug for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CharClass")
+    }
+}
+
+#[derive(Clone, Debug)]
 enum BoundariesClass {
-    AlphanumericWord { option: ValidBoundariesOption },
-    AlphabeticWord { option: ValidBoundariesOption },
-    Detailed { option: ValidBoundariesOption },
+    AlphanumericWord {
+        option: ValidBoundariesOption,
+    },
+    AlphabeticWord {
+        option: ValidBoundariesOption,
+    },
+    Detailed {
+        option: ValidBoundariesOption,
+    },
+    Custom {
+        char_class: CharClass,
+        option: ValidBoundariesOption,
+    },
+    ScriptAware,
     NoClass,
 }
 
+/// Custom classes only compare equal when they share their char class
+/// function, as functions can't be compared.
+impl PartialEq for BoundariesClass {
+    fn eq(&self, other: &BoundariesClass) -> bool {
+        use self::BoundariesClass::*;
+        match (self, other) {
+            (AlphanumericWord { option: a }, AlphanumericWord { option: b })
+            | (AlphabeticWord { option: a }, AlphabeticWord { option: b })
+            | (Detailed { option: a }, Detailed { option: b }) => a == b,
+            (
+                Custom {
+                    char_class: a,
+                    option: option_a,
+                },
+                Custom {
+                    char_class: b,
+                    option: option_b,
+                },
+            ) => Arc::ptr_eq(&a.0, &b.0) && option_a == option_b,
+            (ScriptAware, ScriptAware) | (NoClass, NoClass) => true,
+            _ => false,
+        }
+    }
+}
+
 impl BoundariesClass {
-    fn apply_left(&self, sentence: &str, range: &Range) -> bool {
+    fn apply_left(&self, sentence: &str, range: &Range, word_bounds: &WordBounds) -> bool {
         match self {
             &BoundariesClass::AlphanumericWord { option } => {
                 left_valid_boundaries(sentence, range, &option, &alphanumeric_class)
@@ -20,10 +73,15 @@ impl BoundariesClass {
             &BoundariesClass::Detailed { option } => {
                 left_valid_boundaries(sentence, range, &option, &detailed_class)
             }
+            &BoundariesClass::Custom {
+                ref char_class,
+                option,
+            } => left_valid_boundaries(sentence, range, &option, &*char_class.0),
+            &BoundariesClass::ScriptAware => script_valid_boundary(sentence, range.0, word_bounds),
             &BoundariesClass::NoClass => true,
         }
     }
-    fn apply_right(&self, sentence: &str, range: &Range) -> bool {
+    fn apply_right(&self, sentence: &str, range: &Range, word_bounds: &WordBounds) -> bool {
         match self {
             &BoundariesClass::AlphanumericWord { option } => {
                 right_valid_boundaries(sentence, range, &option, &alphanumeric_class)
@@ -34,18 +92,36 @@ impl BoundariesClass {
             &BoundariesClass::Detailed { option } => {
                 right_valid_boundaries(sentence, range, &option, &detailed_class)
             }
+            &BoundariesClass::Custom {
+                ref char_class,
+                option,
+            } => right_valid_boundaries(sentence, range, &option, &*char_class.0),
+            &BoundariesClass::ScriptAware => script_valid_boundary(sentence, range.1, word_bounds),
             &BoundariesClass::NoClass => true,
         }
     }
 }
 
+/// Checkers with a custom class only compare equal to their clones, see
+/// `BoundariesChecker::custom`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct BoundariesChecker(Vec<BoundariesClass>);
 
 impl BoundariesChecker {
     pub fn check(&self, sentence: &str, range: Range) -> bool {
-        self.0.iter().any(|c| c.apply_left(sentence, &range))
-            && self.0.iter().any(|c| c.apply_right(sentence, &range))
+        self.check_with(sentence, range, &WordBounds::default())
+    }
+
+    /// Same as `check`, reusing the word boundaries of `sentence` already
+    /// computed in `word_bounds`.
+    pub fn check_with(&self, sentence: &str, range: Range, word_bounds: &WordBounds) -> bool {
+        self.0
+            .iter()
+            .any(|c| c.apply_left(sentence, &range, word_bounds))
+            && self
+                .0
+                .iter()
+                .any(|c| c.apply_right(sentence, &range, word_bounds))
     }
 
     pub fn separated_alphanumeric_word() -> BoundariesChecker {
@@ -74,15 +150,190 @@ impl BoundariesChecker {
     pub fn no_check() -> BoundariesChecker {
         BoundariesChecker(vec![BoundariesClass::NoClass])
     }
+
+    /// Checker comparing the classes given by `char_class` to the chars
+    /// around the match boundaries, e.g. a copy of `alphanumeric_class`
+    /// mapping apostrophes to the class of letters keeps "o'clock" whole.
+    pub fn custom<F>(char_class: F, option: ValidBoundariesOption) -> BoundariesChecker
+    where
+        F: Fn(char) -> char + Send + Sync + 'static,
+    {
+        BoundariesChecker(vec![BoundariesClass::Custom {
+            char_class: CharClass(Arc::new(char_class)),
+            option,
+        }])
+    }
+
+    /// Checker for sentences mixing whitespace-delimited words with scripts
+    /// written without spaces (Chinese, Japanese, Thai, Lao, Khmer,
+    /// Burmese). Next to such scripts, boundaries must be Unicode word
+    /// boundaries (UAX #29); elsewhere, they must separate alphanumeric
+    /// words. UAX #29 has no dictionary segmentation, so Han and Thai text
+    /// still breaks between every grapheme cluster: this checker keeps a
+    /// match from splitting a cluster or a Katakana run, not a word.
+    pub fn script_aware() -> BoundariesChecker {
+        BoundariesChecker(vec![BoundariesClass::ScriptAware])
+    }
+
+    /// Checker accepting the boundaries valid for either `self` or `other`.
+    pub fn or(mut self, other: BoundariesChecker) -> BoundariesChecker {
+        self.0.extend(other.0);
+        self
+    }
 }
 
+/// What may separate two consecutive matches of a rule. The default policy
+/// only allows whitespace, of any length.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct AdjacencyPolicy {
+    fillers: Vec<char>,
+    stop_words: Vec<String>,
+    max_gap: Option<usize>,
+}
+
+impl AdjacencyPolicy {
+    pub fn whitespace() -> AdjacencyPolicy {
+        AdjacencyPolicy::default()
+    }
+
+    /// Also allows these characters in the gap, e.g. commas or hyphens.
+    pub fn with_fillers(mut self, fillers: &[char]) -> AdjacencyPolicy {
+        self.fillers.extend(fillers);
+        self
+    }
+
+    /// Also allows these whole words in the gap.
+    pub fn with_stop_words(mut self, stop_words: &[&str]) -> AdjacencyPolicy {
+        self.stop_words
+            .extend(stop_words.iter().map(|w| w.to_string()));
+        self
+    }
+
+    /// Limits the gap to `max_gap` bytes.
+    pub fn with_max_gap(mut self, max_gap: usize) -> AdjacencyPolicy {
+        self.max_gap = Some(max_gap);
+        self
+    }
+
+    /// Byte offsets at which a match adjacent to a match ending at `end`
+    /// may start, in increasing order. With `tokens`, stop words must be
+    /// whole tokens and only the token starts are kept, plus the end of the
+    /// sentence.
+    pub fn adjacent_starts(
+        &self,
+        sentence: &str,
+        end: usize,
+        tokens: Option<&Tokens>,
+    ) -> SmallVec<[usize; 4]> {
+        let mut starts = SmallVec::new();
+        let mut position = end;
+        loop {
+            starts.push(position);
+            let next = match self.gap_step(sentence, position, tokens) {
+                Some(next) => next,
+                None => break,
+            };
+            if self.max_gap.map(|max| next - end > max).unwrap_or(false) {
+                break;
+            }
+            position = next;
+        }
+        if let Some(tokens) = tokens {
+            starts.retain(|start| *start == sentence.len() || tokens.is_start(*start));
+        }
+        starts
+    }
+
+    /// Offset after the filler at `position`, if any.
+    fn gap_step(&self, sentence: &str, position: usize, tokens: Option<&Tokens>) -> Option<usize> {
+        let rest = &sentence[position..];
+        if let Some(c) = rest.chars().next() {
+            if c.is_whitespace() || self.fillers.contains(&c) {
+                return Some(position + c.len_utf8());
+            }
+        }
+        let is_word_char = |c: Option<char>| c.map(|c| c.is_alphanumeric()).unwrap_or(false);
+        if tokens.is_none() && is_word_char(sentence[..position].chars().next_back()) {
+            return None;
+        }
+        self.stop_words
+            .iter()
+            .find(|w| {
+                !w.is_empty()
+                    && rest.starts_with(w.as_str())
+                    && match tokens {
+                        Some(tokens) => tokens.is_aligned(Range(position, position + w.len())),
+                        None => !is_word_char(rest[w.len()..].chars().next()),
+                    }
+            })
+            .map(|w| position + w.len())
+    }
+}
+
+/// Number of char insertions, deletions and substitutions turning `a`
+/// into `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// `text` lowercased char by char, with the byte offset in `text` of each
+/// byte offset of the lowercased text, `None` inside the lowercase form of a
+/// char.
+pub fn lowercase_with_offsets(text: &str) -> (String, Vec<Option<usize>>) {
+    let mut lowercased = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    for (ix, c) in text.char_indices() {
+        offsets.push(Some(ix));
+        lowercased.extend(c.to_lowercase());
+        offsets.resize(lowercased.len(), None);
+    }
+    offsets.push(Some(text.len()));
+    (lowercased, offsets)
+}
+
+/// Ranges of the maximal runs of alphanumeric chars of `sentence`.
+pub fn word_ranges(sentence: &str) -> Vec<Range> {
+    let mut words = vec![];
+    let mut start = None;
+    for (ix, c) in sentence.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(ix),
+            (false, Some(s)) => {
+                words.push(Range(s, ix));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push(Range(s, sentence.len()));
+    }
+    words
+}
+
+/// When a match boundary is valid, given the classes of the chars on both
+/// of its sides.
 #[derive(Copy, Clone, Debug, PartialEq)]
-enum ValidBoundariesOption {
+pub enum ValidBoundariesOption {
+    /// The classes differ.
     OnCharClassChange,
+    /// The classes are the same, or the boundary is the sentence end.
     OnSameCharClass,
 }
 
-fn alphabetic_class(c: char) -> char {
+/// 'A' for alphabetic chars, 'O' for the others.
+pub fn alphabetic_class(c: char) -> char {
     if c.is_alphabetic() {
         'A'
     } else {
@@ -90,7 +341,8 @@ fn alphabetic_class(c: char) -> char {
     }
 }
 
-fn alphanumeric_class(c: char) -> char {
+/// 'A' for alphanumeric chars, the char itself for the others.
+pub fn alphanumeric_class(c: char) -> char {
     if c.is_alphanumeric() {
         'A'
     } else {
@@ -98,7 +350,9 @@ fn alphanumeric_class(c: char) -> char {
     }
 }
 
-fn detailed_class(c: char) -> char {
+/// 'u' for uppercase chars, 'l' for lowercase ones, 'd' for digits, the
+/// char itself for the others.
+pub fn detailed_class(c: char) -> char {
     if c.is_uppercase() {
         'u'
     } else if c.is_lowercase() {
@@ -110,14 +364,62 @@ fn detailed_class(c: char) -> char {
     }
 }
 
-fn right_valid_boundaries<CharClass>(
+/// Whether `c` belongs to a script whose words are not separated by spaces.
+pub fn is_unsegmented_script(c: char) -> bool {
+    matches!(
+        c.script(),
+        Script::Han
+            | Script::Hiragana
+            | Script::Katakana
+            | Script::Thai
+            | Script::Lao
+            | Script::Khmer
+            | Script::Myanmar
+    )
+}
+
+/// Unicode word boundaries (UAX #29) of a sentence, only computed the first
+/// time a script aware checker needs them.
+#[derive(Debug, Default)]
+pub struct WordBounds(RefCell<Option<Vec<usize>>>);
+
+impl WordBounds {
+    fn contains(&self, sentence: &str, position: usize) -> bool {
+        self.0
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                sentence
+                    .split_word_bound_indices()
+                    .map(|(start, _)| start)
+                    .collect()
+            })
+            .binary_search(&position)
+            .is_ok()
+    }
+}
+
+fn script_valid_boundary(sentence: &str, position: usize, word_bounds: &WordBounds) -> bool {
+    let before = sentence[..position].chars().next_back();
+    let after = sentence[position..].chars().next();
+    let (before, after) = match (before, after) {
+        (Some(before), Some(after)) => (before, after),
+        _ => return true,
+    };
+    if is_unsegmented_script(before) || is_unsegmented_script(after) {
+        word_bounds.contains(sentence, position)
+    } else {
+        alphanumeric_class(before) != alphanumeric_class(after)
+    }
+}
+
+fn right_valid_boundaries<F>(
     sentence: &str,
     range: &Range,
     option: &ValidBoundariesOption,
-    char_class: &CharClass,
+    char_class: &F,
 ) -> bool
 where
-    CharClass: Fn(char) -> char,
+    F: Fn(char) -> char + ?Sized,
 {
     let last_mine = sentence[range.0..range.1]
         .chars()
@@ -131,14 +433,14 @@ where
     }
 }
 
-fn left_valid_boundaries<CharClass>(
+fn left_valid_boundaries<F>(
     sentence: &str,
     range: &Range,
     option: &ValidBoundariesOption,
-    char_class: &CharClass,
+    char_class: &F,
 ) -> bool
 where
-    CharClass: Fn(char) -> char,
+    F: Fn(char) -> char + ?Sized,
 {
     let first_mine = sentence[range.0..range.1].chars().next().map(char_class); // Some(c)
     let last_before = sentence[..range.0].chars().next_back().map(char_class); // Option(c)
@@ -153,6 +455,65 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_adjacency_policy() {
+        let whitespace = AdjacencyPolicy::whitespace();
+        assert_eq!(
+            &[2, 3, 4][..],
+            &*whitespace.adjacent_starts("12  000", 2, None)
+        );
+        assert_eq!(&[2][..], &*whitespace.adjacent_starts("12, 000", 2, None));
+
+        let fillers = AdjacencyPolicy::whitespace().with_fillers(&[',', '-']);
+        assert_eq!(
+            &[2, 3, 4][..],
+            &*fillers.adjacent_starts("12, 000", 2, None)
+        );
+        assert_eq!(
+            &[6, 7, 8, 9][..],
+            &*fillers.adjacent_starts("monday - friday", 6, None)
+        );
+
+        let stop_words = AdjacencyPolicy::whitespace().with_stop_words(&["of"]);
+        assert_eq!(
+            &[3, 4, 6, 7][..],
+            &*stop_words.adjacent_starts("3rd of may", 3, None)
+        );
+        assert_eq!(
+            &[3, 4][..],
+            &*stop_words.adjacent_starts("3rd offer", 3, None)
+        );
+        assert_eq!(&[2][..], &*stop_words.adjacent_starts("3rof may", 2, None));
+
+        let max_gap = AdjacencyPolicy::whitespace().with_max_gap(1);
+        assert_eq!(&[2, 3][..], &*max_gap.adjacent_starts("12   000", 2, None));
+
+        let tokens = Tokens::new(vec![Range(0, 2), Range(4, 7)]);
+        let starts = whitespace.adjacent_starts("12  000", 2, Some(&tokens));
+        assert_eq!(&[4][..], &*starts);
+        let tokens = Tokens::new(vec![Range(0, 3), Range(4, 10)]);
+        let starts = stop_words.adjacent_starts("3rd of-may", 3, Some(&tokens));
+        assert_eq!(&[4][..], &*starts);
+        let tokens = Tokens::new(vec![Range(0, 3), Range(4, 6), Range(6, 7), Range(7, 10)]);
+        let starts = stop_words.adjacent_starts("3rd of-may", 3, Some(&tokens));
+        assert_eq!(&[4, 6][..], &*starts);
+        let tokens = Tokens::new(vec![Range(0, 3)]);
+        let starts = whitespace.adjacent_starts("3rd ", 3, Some(&tokens));
+        assert_eq!(&[4][..], &*starts);
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(0, levenshtein("monday", "monday"));
+        assert_eq!(1, levenshtein("tomorow", "tomorrow"));
+        assert_eq!(2, levenshtein("wensday", "wednesday"));
+        assert_eq!(3, levenshtein("", "été"));
+        assert_eq!(
+            vec![Range(0, 3), Range(4, 9), Range(11, 14)],
+            word_ranges("foo bar12, é1")
+        );
+    }
+
     #[test]
     fn test_valid_boundaries_alphanumeric() {
         let checker = BoundariesChecker::separated_alphanumeric_word();
@@ -204,6 +565,98 @@ mod tests {
         assert_eq!(true, checker.check("aeç def ret", Range(4, 8))); // " def "
     }
 
+    #[test]
+    fn test_valid_boundaries_custom() {
+        fn apostrophe_word_class(c: char) -> char {
+            if c == '\'' || c == '-' {
+                'A'
+            } else {
+                alphanumeric_class(c)
+            }
+        }
+        let checker = BoundariesChecker::custom(
+            apostrophe_word_class,
+            ValidBoundariesOption::OnCharClassChange,
+        );
+        assert!(checker.check("at five o'clock", Range(8, 15))); // "o'clock"
+        assert!(!checker.check("at five o'clock", Range(10, 15))); // "clock"
+        assert!(!checker.check("a well-known fact", Range(7, 12))); // "known"
+        assert!(checker.check("a well-known fact", Range(2, 12))); // "well-known"
+
+        let currency = BoundariesChecker::custom(
+            |c| {
+                if c == '$' || c == '€' {
+                    'd'
+                } else {
+                    detailed_class(c)
+                }
+            },
+            ValidBoundariesOption::OnCharClassChange,
+        );
+        assert!(!currency.check("for 12€", Range(4, 6))); // "12"
+        assert!(currency.check("for 12€", Range(4, 9))); // "12€"
+
+        let either = currency.or(checker);
+        assert!(either.check("for 12€", Range(4, 6))); // "12"
+        assert!(!either.check("abc def ret", Range(2, 8))); // "c def r"
+        assert!(either == either.clone());
+        assert!(
+            BoundariesChecker::custom(detailed_class, ValidBoundariesOption::OnCharClassChange)
+                != BoundariesChecker::custom(
+                    detailed_class,
+                    ValidBoundariesOption::OnCharClassChange
+                )
+        );
+
+        let joiners = String::from("'-");
+        let joined = BoundariesChecker::custom(
+            move |c| {
+                if joiners.contains(c) {
+                    'A'
+                } else {
+                    alphanumeric_class(c)
+                }
+            },
+            ValidBoundariesOption::OnCharClassChange,
+        );
+        assert!(joined.check("a well-known fact", Range(2, 12))); // "well-known"
+        assert!(!joined.check("a well-known fact", Range(7, 12))); // "known"
+    }
+
+    #[test]
+    fn test_valid_boundaries_script_aware() {
+        let checker = BoundariesChecker::script_aware();
+        let zh = "明天下午3点开会";
+        assert!(checker.check(zh, Range(6, 12))); // "下午"
+        assert!(checker.check(zh, Range(12, 16))); // "3点"
+        assert!(checker.check(zh, Range(0, zh.len())));
+
+        let ja = "東京でiPhoneを買った";
+        assert!(checker.check(ja, Range(9, 15))); // "iPhone"
+        assert!(!checker.check(ja, Range(10, 15))); // "Phone"
+        assert!(checker.check(ja, Range(0, 6))); // "東京"
+        let ja = "コンピューターで";
+        assert!(checker.check(ja, Range(0, 21))); // "コンピューター"
+        assert!(!checker.check(ja, Range(6, 21))); // "ピューター"
+        assert!(!checker.check(ja, Range(0, 9))); // "コンピ"
+
+        // the word boundaries of a sentence are computed once for all checks
+        let word_bounds = WordBounds::default();
+        assert!(checker.check_with(ja, Range(0, 21), &word_bounds));
+        assert!(!checker.check_with(ja, Range(6, 21), &word_bounds));
+
+        // "นี้" is a consonant followed by a vowel sign and a tone mark
+        let th = "พรุ่งนี้ 3 โมง";
+        assert!(checker.check(th, Range(15, 24))); // "นี้"
+        assert!(!checker.check(th, Range(15, 18))); // "น"
+        assert!(!checker.check(th, Range(0, 21))); // "พรุ่งนี"
+        assert!(checker.check(th, Range(25, 26))); // "3"
+
+        let en = "abc def123 ret";
+        assert!(checker.check(en, Range(4, 10))); // "def123"
+        assert!(!checker.check(en, Range(4, 7))); // "def"
+    }
+
     #[test]
     fn test_valid_boundaries_no_check() {
         let checker = BoundariesChecker::no_check();
@@ -1,32 +1,45 @@
+extern crate aho_corasick;
 #[macro_use]
 extern crate failure;
 pub extern crate regex;
 extern crate smallvec;
 extern crate string_interner;
+extern crate unicode_script;
+extern crate unicode_segmentation;
 
 mod builder;
 mod helpers;
+mod parsing;
 pub mod pattern;
 mod range;
 pub mod rule;
 mod stash;
+mod tokenizer;
 
 pub use builder::RuleSetBuilder;
-pub use helpers::BoundariesChecker;
+pub use helpers::{
+    alphabetic_class, alphanumeric_class, detailed_class, is_unsegmented_script, AdjacencyPolicy,
+    BoundariesChecker, ValidBoundariesOption, WordBounds,
+};
+pub use parsing::{EvaluationStrategy, ParsingConfig, ParsingLimit, ParsingReport};
 use pattern::Pattern;
 use pattern::TerminalPattern;
-pub use range::Range;
-use rule::Rule;
+pub use range::{OffsetTable, Range};
 use rule::TerminalRule;
+use rule::{ForestNodes, Rule, RuleOutput};
 pub use rule::{RuleError, RuleResult};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use stash::Stash;
-pub use stash::{InnerStashIndexable, StashIndexable};
-use std::collections::HashSet;
+pub use stash::{
+    ChildrenRefs, ForestNode, InnerStashIndexable, NodeId, NodeRef, PredicateKey, StashIndexable,
+    TextCacheKey,
+};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::{cell, rc};
+use std::{cell, sync};
 use string_interner::StringInterner;
+pub use tokenizer::{Tokenizer, Tokens, WordTokenizer};
 
 pub type CoreResult<T> = Result<T, ::failure::Error>;
 
@@ -53,7 +66,7 @@ pub trait NodePayload: Clone {
     fn extract_payload(&self) -> Option<Self::Payload>;
 }
 
-pub type ChildrenNodes<Payload> = SmallVec<[rc::Rc<Node<Payload>>; 2]>;
+pub type ChildrenNodes<Payload> = SmallVec<[sync::Arc<Node<Payload>>; 2]>;
 
 #[derive(Copy, Ord, Eq, Clone, PartialEq, PartialOrd, Debug, Hash, Serialize, Deserialize)]
 pub struct Sym(usize);
@@ -124,6 +137,7 @@ pub struct Node<Payload: Clone> {
     pub byte_range: Range,
     pub payload: Option<Payload>,
     pub children: ChildrenNodes<Payload>,
+    edit_distance: Option<usize>,
 }
 
 impl<Payload: Clone> Node<Payload> {
@@ -132,15 +146,31 @@ impl<Payload: Clone> Node<Payload> {
         byte_range: Range,
         payload: Option<Payload>,
         children: ChildrenNodes<Payload>,
-    ) -> rc::Rc<Node<Payload>> {
-        rc::Rc::new(Node {
+    ) -> sync::Arc<Node<Payload>> {
+        sync::Arc::new(Node {
             rule_sym: sym,
             byte_range,
             payload,
             children,
+            edit_distance: None,
         })
     }
 
+    fn text(sym: Sym, byte_range: Range, edit_distance: Option<usize>) -> sync::Arc<Node<Payload>> {
+        sync::Arc::new(Node {
+            rule_sym: sym,
+            byte_range,
+            payload: None,
+            children: SmallVec::new(),
+            edit_distance,
+        })
+    }
+
+    /// Edit distance of a fuzzy text match, for text leaves
+    pub fn edit_distance(&self) -> Option<usize> {
+        self.edit_distance
+    }
+
     pub fn height(&self) -> usize {
         1 + self.children.iter().map(|c| c.height()).max().unwrap_or(0)
     }
@@ -164,21 +194,29 @@ impl<Payload: Clone> Node<Payload> {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ParsedNode<V: NodePayload> {
-    pub root_node: rc::Rc<Node<V::Payload>>,
+    pub root_node: sync::Arc<Node<V::Payload>>,
     pub value: V,
 }
 
-impl<V: NodePayload> ParsedNode<V> {
-    fn new(
-        sym: Sym,
-        v: V,
-        r: Range,
-        payload: Option<V::Payload>,
-        children: ChildrenNodes<V::Payload>,
-    ) -> ParsedNode<V> {
-        ParsedNode {
-            root_node: Node::new(sym, r, payload, children),
-            value: v,
+/// Trees of all the nodes built from a sentence, including the ones which
+/// are only parts of other trees. Subtrees are shared between the trees.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Forest<V: NodePayload> {
+    /// Nodes by `NodeId`
+    pub nodes: Vec<ParsedNode<V>>,
+    /// References to the children of the nodes, by `NodeId`
+    pub children: Vec<ChildrenRefs>,
+    /// Positions in `nodes` of the nodes returned by `apply_all`, the ones
+    /// with valid match boundaries
+    pub roots: Vec<usize>,
+}
+
+impl<V: NodePayload> Forest<V> {
+    /// Id of the child at `index` of the node `id`, `None` for text leaves.
+    pub fn child_id(&self, id: NodeId, index: usize) -> Option<NodeId> {
+        match self.children[id.0].get(index) {
+            Some(&NodeRef::Node(child)) => Some(child),
+            _ => None,
         }
     }
 }
@@ -188,6 +226,12 @@ pub struct RuleSet<StashValue: NodePayload + StashIndexable> {
     composition_rules: Vec<Box<dyn Rule<StashValue>>>,
     terminal_rules: Vec<Box<dyn TerminalRule<StashValue>>>,
     match_boundaries: BoundariesChecker,
+    config: ParsingConfig,
+    rules_by_index: HashMap<StashValue::Index, Vec<usize>>,
+    /// Rules which may consume nodes of any index
+    rules_for_any_index: Vec<usize>,
+    text_scan: Option<regex::RegexSet>,
+    tokenizer: Option<Box<dyn Tokenizer + Send + Sync>>,
 }
 
 impl<StashValue: NodePayload + StashIndexable> RuleSet<StashValue> {
@@ -195,57 +239,198 @@ impl<StashValue: NodePayload + StashIndexable> RuleSet<StashValue> {
         &self,
         stash: &mut Stash<StashValue>,
         sentence: &str,
+        report: &mut ParsingReport,
     ) -> CoreResult<()> {
         let mut produced_nodes = vec![];
         for rule in &self.terminal_rules {
-            produced_nodes.extend(rule.apply(stash, sentence)?.nodes);
+            let output = rule.apply(stash, sentence)?;
+            report.rule_applications += 1;
+            produced_nodes.extend(self.limit_rule_output(output, report).0);
         }
         stash.extend(produced_nodes);
         Ok(())
     }
 
+    /// Applies the composition rules both active in `rules_mask_status` and
+    /// scheduled in `agenda`. Rules whose output got truncated stay
+    /// scheduled, the others are unscheduled.
     fn apply_composition_rules(
         &self,
         stash: &mut Stash<StashValue>,
         sentence: &str,
-        rules_mask_status: &mut Vec<ParsingStatus>,
+        rules_mask_status: &mut [ParsingStatus],
+        agenda: &mut [bool],
+        report: &mut ParsingReport,
     ) -> CoreResult<()> {
         let mut produced_nodes = vec![];
         for (idx, rule) in self.composition_rules.iter().enumerate() {
-            if rules_mask_status[idx].is_continue() {
+            if rules_mask_status[idx].is_continue() && agenda[idx] {
                 let output = rule.apply(stash, sentence)?;
+                report.rule_applications += 1;
                 rules_mask_status[idx] = output.status;
-                produced_nodes.extend(output.nodes);
+                let (nodes, truncated) = self.limit_rule_output(output, report);
+                agenda[idx] = truncated;
+                produced_nodes.extend(nodes);
             }
         }
         stash.extend(produced_nodes);
         Ok(())
     }
 
+    /// Schedules the rules which may consume the nodes of the stash from
+    /// `from` onwards.
+    fn schedule_consumers(&self, stash: &Stash<StashValue>, from: usize, agenda: &mut [bool]) {
+        if stash.len() > from {
+            for idx in &self.rules_for_any_index {
+                agenda[*idx] = true;
+            }
+        }
+        for node in stash.iter().skip(from) {
+            if let Some(rules) = self.rules_by_index.get(&node.value.index()) {
+                for idx in rules {
+                    agenda[*idx] = true;
+                }
+            }
+        }
+    }
+
+    fn limit_rule_output(
+        &self,
+        output: RuleOutput<StashValue>,
+        report: &mut ParsingReport,
+    ) -> (ForestNodes<StashValue>, bool) {
+        report.duplicates_suppressed += output.duplicates;
+        let mut nodes = output.nodes;
+        let mut truncated = false;
+        if let Some(max_tree_height) = self.config.max_tree_height {
+            let produced = nodes.len();
+            nodes.retain(|n| n.height <= max_tree_height);
+            if nodes.len() < produced {
+                report.hit(ParsingLimit::TreeHeight);
+            }
+        }
+        if let Some(max_rule_output) = self.config.max_rule_output {
+            if nodes.len() > max_rule_output {
+                nodes.truncate(max_rule_output);
+                report.hit(ParsingLimit::RuleOutput);
+                truncated = true;
+            }
+        }
+        (nodes, truncated)
+    }
+
     pub fn apply_all(&self, sentence: &str) -> CoreResult<Vec<ParsedNode<StashValue>>> {
-        let iterations_max = 10;
-        let max_stash_size = 600;
+        Ok(self.apply_all_with_report(sentence)?.0)
+    }
+
+    pub fn apply_all_with_report(
+        &self,
+        sentence: &str,
+    ) -> CoreResult<(Vec<ParsedNode<StashValue>>, ParsingReport)> {
+        let (mut stash, report) = self.parse(sentence)?;
+        let word_bounds = stash.take_word_bounds();
+        let nodes = stash.into_parsed_nodes(|node| {
+            self.match_boundaries
+                .check_with(sentence, node.byte_range, &word_bounds)
+        });
+        Ok((nodes, report))
+    }
+
+    /// All the nodes built from `sentence`, whether their match boundaries
+    /// are valid or not.
+    pub fn forest(&self, sentence: &str) -> CoreResult<Forest<StashValue>> {
+        let (mut stash, _) = self.parse(sentence)?;
+        let word_bounds = stash.take_word_bounds();
+        let children = stash.iter().map(|n| n.children.clone()).collect();
+        let nodes = stash.into_parsed_nodes(|_| true);
+        let roots = nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| {
+                self.match_boundaries
+                    .check_with(sentence, n.root_node.byte_range, &word_bounds)
+            })
+            .map(|(position, _)| position)
+            .collect();
+        Ok(Forest {
+            nodes,
+            children,
+            roots,
+        })
+    }
+
+    fn parse(&self, sentence: &str) -> CoreResult<(Stash<StashValue>, ParsingReport)> {
+        let mut report = ParsingReport::default();
         let mut stash = Stash::default();
+        if let Some(ref text_scan) = self.text_scan {
+            stash.set_text_scan(text_scan.matches(sentence));
+        }
+        if let Some(tokens) = self.tokens(sentence) {
+            stash.set_tokens(tokens);
+        }
 
-        self.apply_terminal_rules(&mut stash, sentence)?;
+        self.apply_terminal_rules(&mut stash, sentence, &mut report)?;
         let mut previous_stash_size = stash.len();
 
         let mut rules_mask_status = vec![ParsingStatus::Continue; self.composition_rules.len()];
+        let mut agenda = vec![true; self.composition_rules.len()];
 
-        for _ in 0..iterations_max {
-            self.apply_composition_rules(&mut stash, sentence, &mut rules_mask_status)?;
-            if stash.len() <= previous_stash_size || stash.len() > max_stash_size {
+        loop {
+            if report.iterations >= self.config.iterations_max {
+                report.hit(ParsingLimit::Iterations);
+                break;
+            }
+            self.apply_composition_rules(
+                &mut stash,
+                sentence,
+                &mut rules_mask_status,
+                &mut agenda,
+                &mut report,
+            )?;
+            report.iterations += 1;
+            if stash.len() <= previous_stash_size {
                 break;
             }
+            if stash.len() > self.config.max_stash_size {
+                report.hit(ParsingLimit::StashSize);
+                break;
+            }
+            match self.config.strategy {
+                EvaluationStrategy::FixedPoint => {
+                    agenda.iter_mut().for_each(|scheduled| *scheduled = true)
+                }
+                EvaluationStrategy::Agenda => {
+                    self.schedule_consumers(&stash, previous_stash_size, &mut agenda)
+                }
+            }
             previous_stash_size = stash.len();
         }
-        Ok(stash
-            .into_iter()
-            .filter(|pn| {
-                self.match_boundaries
-                    .check(sentence, pn.root_node.byte_range)
-            })
-            .collect())
+        report.stash_size = stash.len();
+        let (hits, misses) = stash.predicate_memo_stats();
+        report.predicate_memo_hits = hits;
+        report.predicate_memo_misses = misses;
+        Ok((stash, report))
+    }
+
+    pub fn config(&self) -> &ParsingConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ParsingConfig) {
+        self.config = config
+    }
+
+    /// Only keeps the text matches aligned on the tokens of `tokenizer`, and
+    /// only lets the matches of a rule be separated by whole tokens.
+    pub fn set_tokenizer<T: Tokenizer + Send + Sync + 'static>(&mut self, tokenizer: T) {
+        self.tokenizer = Some(Box::new(tokenizer))
+    }
+
+    /// Tokens of `sentence`, if the rule set has a tokenizer.
+    pub fn tokens(&self, sentence: &str) -> Option<Tokens> {
+        self.tokenizer
+            .as_ref()
+            .map(|t| Tokens::new(t.tokenize(sentence)))
     }
 
     pub fn resolve_sym(&self, sym: &Sym) -> Option<&str> {
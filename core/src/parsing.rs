@@ -0,0 +1,101 @@
+/// Limits applied by `RuleSet::apply_all` while looking for the fixed point
+/// of the composition rules.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ParsingConfig {
+    /// Maximum number of passes over the composition rules
+    pub iterations_max: usize,
+    /// Parsing stops after the pass that makes the stash grow past this size
+    pub max_stash_size: usize,
+    /// Maximum number of nodes a single rule may produce in one pass
+    pub max_rule_output: Option<usize>,
+    /// Produced nodes with a deeper parsing tree are dropped
+    pub max_tree_height: Option<usize>,
+    /// How composition rules are scheduled between passes
+    pub strategy: EvaluationStrategy,
+}
+
+impl Default for ParsingConfig {
+    fn default() -> ParsingConfig {
+        ParsingConfig {
+            iterations_max: 10,
+            max_stash_size: 600,
+            max_rule_output: None,
+            max_tree_height: None,
+            strategy: EvaluationStrategy::FixedPoint,
+        }
+    }
+}
+
+/// Scheduling of the composition rules. Both strategies produce the same
+/// nodes, in the same order.
+#[derive(Debug, PartialEq, Clone, Copy, Hash, Eq)]
+pub enum EvaluationStrategy {
+    /// Every composition rule is applied on each pass
+    FixedPoint,
+    /// After the first pass, a rule is only applied again if the previous
+    /// pass produced nodes its patterns may consume
+    Agenda,
+}
+
+/// Limit of a `ParsingConfig` that has been reached during a parsing.
+#[derive(Debug, PartialEq, Clone, Copy, Hash, Eq)]
+pub enum ParsingLimit {
+    Iterations,
+    StashSize,
+    RuleOutput,
+    TreeHeight,
+}
+
+/// Summary of a parsing, telling whether some matches may have been lost
+/// because of the `ParsingConfig`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ParsingReport {
+    /// Number of passes over the composition rules
+    pub iterations: usize,
+    /// Number of nodes in the stash at the end of the parsing
+    pub stash_size: usize,
+    /// Number of times a rule has been applied on the stash
+    pub rule_applications: usize,
+    /// Number of rule matches dropped as their node was already produced
+    pub duplicates_suppressed: usize,
+    /// Number of node pattern predicates answered from the memoized results
+    pub predicate_memo_hits: usize,
+    /// Number of node pattern predicates that had to test new nodes
+    pub predicate_memo_misses: usize,
+    /// Limits reached during the parsing, in the order they were first hit
+    pub limits: Vec<ParsingLimit>,
+}
+
+impl ParsingReport {
+    pub fn hit(&mut self, limit: ParsingLimit) {
+        if !self.limits.contains(&limit) {
+            self.limits.push(limit)
+        }
+    }
+
+    pub fn has_hit(&self, limit: ParsingLimit) -> bool {
+        self.limits.contains(&limit)
+    }
+
+    /// Limit which ended the composition loop early, if any.
+    pub fn stopped_by(&self) -> Option<ParsingLimit> {
+        self.limits
+            .iter()
+            .cloned()
+            .find(|l| *l == ParsingLimit::Iterations || *l == ParsingLimit::StashSize)
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        !self.limits.is_empty()
+    }
+
+    /// Share of the node pattern predicates answered from memoized results.
+    pub fn predicate_memo_hit_rate(&self) -> Option<f32> {
+        let total = self.predicate_memo_hits + self.predicate_memo_misses;
+        if total == 0 {
+            None
+        } else {
+            Some(self.predicate_memo_hits as f32 / total as f32)
+        }
+    }
+}
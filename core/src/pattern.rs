@@ -1,28 +1,72 @@
-use crate::helpers::BoundariesChecker;
+use crate::helpers::{
+    levenshtein, lowercase_with_offsets, word_ranges, AdjacencyPolicy, BoundariesChecker,
+    WordBounds,
+};
 use crate::range::Range;
+use crate::rule::{successor_starts, Successors};
 use crate::{
-    AttemptFrom, CoreResult, InnerStashIndexable, Node, NodePayload, ParsedNode, ParsingStatus,
-    SendSyncPhantomData, Stash, StashIndexable, Sym,
+    AttemptFrom, ChildrenRefs, CoreResult, InnerStashIndexable, NodeId, NodePayload, NodeRef,
+    ParsingStatus, PredicateKey, SendSyncPhantomData, Stash, StashIndexable, Sym, TextCacheKey,
+    Tokens,
 };
+use aho_corasick::AhoCorasick;
 use smallvec::SmallVec;
-use std::rc;
+use std::collections::{HashMap, HashSet};
 use std::slice::Iter;
 use std::vec::IntoIter;
 
 pub trait Match: Clone {
     type NV: Clone;
+    /// Range of the match, an empty match is zero-width at the offset where
+    /// it was tried.
     fn byte_range(&self) -> Range;
-    fn to_node(&self) -> rc::Rc<Node<Self::NV>>;
+    /// Appends the children this match gives to the node produced by a rule.
+    fn push_node_refs(&self, refs: &mut ChildrenRefs);
+
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Range actually covered by the match, `None` for an empty match.
+    fn covered_range(&self) -> Option<Range> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.byte_range())
+        }
+    }
+}
+
+/// Match of a node of the stash, as seen through the type `V`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeMatch<V: NodePayload> {
+    pub node_id: NodeId,
+    pub byte_range: Range,
+    pub value: V,
 }
 
-impl<V: NodePayload> Match for ParsedNode<V> {
+impl<V: NodePayload> NodeMatch<V> {
+    pub fn new(node_id: NodeId, byte_range: Range, value: V) -> NodeMatch<V> {
+        NodeMatch {
+            node_id,
+            byte_range,
+            value,
+        }
+    }
+
+    pub fn node_ref(&self) -> NodeRef {
+        NodeRef::Node(self.node_id)
+    }
+}
+
+impl<V: NodePayload> Match for NodeMatch<V> {
     type NV = V::Payload;
     fn byte_range(&self) -> Range {
-        self.root_node.byte_range
+        self.byte_range
     }
 
-    fn to_node(&self) -> rc::Rc<Node<Self::NV>> {
-        self.root_node.clone()
+    fn push_node_refs(&self, refs: &mut ChildrenRefs) {
+        refs.push(self.node_ref())
     }
 }
 
@@ -30,6 +74,10 @@ impl<V: NodePayload> Match for ParsedNode<V> {
 pub struct Text<V: NodePayload> {
     pub groups: SmallVec<[Range; 4]>,
     pub byte_range: Range,
+    /// Id of the matched entry, for gazetteer and fuzzy matches
+    pub entry: Option<usize>,
+    /// Edit distance to the matched entry, for fuzzy matches
+    pub edit_distance: Option<usize>,
     pattern_sym: Sym,
     _phantom: SendSyncPhantomData<V>,
 }
@@ -39,10 +87,21 @@ impl<V: NodePayload> Text<V> {
         Text {
             groups,
             byte_range,
+            entry: None,
+            edit_distance: None,
             pattern_sym,
             _phantom: SendSyncPhantomData::new(),
         }
     }
+
+    pub fn node_ref(&self) -> NodeRef {
+        match self.edit_distance {
+            Some(edit_distance) => {
+                NodeRef::FuzzyText(self.pattern_sym, self.byte_range, edit_distance)
+            }
+            None => NodeRef::Text(self.pattern_sym, self.byte_range),
+        }
+    }
 }
 
 impl<V: NodePayload> Match for Text<V> {
@@ -51,13 +110,75 @@ impl<V: NodePayload> Match for Text<V> {
         self.byte_range
     }
 
-    fn to_node(&self) -> rc::Rc<Node<Self::NV>> {
-        rc::Rc::new(Node {
-            rule_sym: self.pattern_sym,
-            byte_range: self.byte_range(),
-            payload: None,
-            children: SmallVec::new(),
-        })
+    fn push_node_refs(&self, refs: &mut ChildrenRefs) {
+        refs.push(self.node_ref())
+    }
+}
+
+/// Match of a pattern with its type erased, so the matches of heterogenous
+/// patterns can be held in a same sequence.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnyMatch<StashValue: NodePayload> {
+    Text(Text<StashValue>),
+    Node(NodeMatch<StashValue>),
+    AnyOf(Box<AnyOfMatch<StashValue>>),
+    Optional(Box<OptionalMatch<AnyMatch<StashValue>>>),
+    Repeated(RepeatedMatch<AnyMatch<StashValue>>),
+}
+
+impl<StashValue: NodePayload> Match for AnyMatch<StashValue> {
+    type NV = StashValue::Payload;
+    fn byte_range(&self) -> Range {
+        match self {
+            AnyMatch::Text(text) => text.byte_range(),
+            AnyMatch::Node(node) => node.byte_range(),
+            AnyMatch::AnyOf(any_of) => any_of.byte_range(),
+            AnyMatch::Optional(optional) => optional.byte_range(),
+            AnyMatch::Repeated(repeated) => repeated.byte_range(),
+        }
+    }
+
+    fn push_node_refs(&self, refs: &mut ChildrenRefs) {
+        match self {
+            AnyMatch::Text(text) => text.push_node_refs(refs),
+            AnyMatch::Node(node) => node.push_node_refs(refs),
+            AnyMatch::AnyOf(any_of) => any_of.push_node_refs(refs),
+            AnyMatch::Optional(optional) => optional.push_node_refs(refs),
+            AnyMatch::Repeated(repeated) => repeated.push_node_refs(refs),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            AnyMatch::Text(_) | AnyMatch::Node(_) => false,
+            AnyMatch::AnyOf(any_of) => any_of.is_empty(),
+            AnyMatch::Optional(optional) => optional.is_empty(),
+            AnyMatch::Repeated(repeated) => repeated.is_empty(),
+        }
+    }
+}
+
+pub trait IntoAnyMatch<StashValue: NodePayload>: Match {
+    fn into_any_match(self) -> AnyMatch<StashValue>;
+}
+
+impl<StashValue: NodePayload> IntoAnyMatch<StashValue> for Text<StashValue> {
+    fn into_any_match(self) -> AnyMatch<StashValue> {
+        AnyMatch::Text(self)
+    }
+}
+
+impl<StashValue, V> IntoAnyMatch<StashValue> for NodeMatch<V>
+where
+    V: NodePayload,
+    StashValue: NodePayload + From<V>,
+{
+    fn into_any_match(self) -> AnyMatch<StashValue> {
+        AnyMatch::Node(NodeMatch::new(
+            self.node_id,
+            self.byte_range,
+            self.value.into(),
+        ))
     }
 }
 
@@ -108,6 +229,21 @@ impl<M> PredicateMatches<M> {
     pub fn into_iter(self) -> IntoIter<M> {
         self.matches.into_iter()
     }
+
+    fn into_any_matches<StashValue>(self) -> PredicateMatches<AnyMatch<StashValue>>
+    where
+        StashValue: NodePayload,
+        M: IntoAnyMatch<StashValue>,
+    {
+        PredicateMatches {
+            matches: self
+                .matches
+                .into_iter()
+                .map(|m| m.into_any_match())
+                .collect(),
+            status: self.status,
+        }
+    }
 }
 
 pub trait Pattern<StashValue: NodePayload + StashIndexable>: Send + Sync {
@@ -117,6 +253,51 @@ pub trait Pattern<StashValue: NodePayload + StashIndexable>: Send + Sync {
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<Self::M>>;
+
+    /// Indexes of the stash nodes this pattern may match, `None` if it may
+    /// match nodes of any index. Patterns only matching text return an
+    /// empty list.
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        None
+    }
+
+    /// Matches starting at one of the `starts` byte offsets, used by
+    /// composition rules to only look up the successors of their previous
+    /// matches. Patterns which may match nothing also match it at each of
+    /// the `starts`.
+    fn predicate_starting_at(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+        starts: &HashSet<usize>,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        let mut matches = self.predicate(stash, sentence)?;
+        matches
+            .matches
+            .retain(|m| starts.contains(&m.byte_range().0));
+        Ok(matches)
+    }
+
+    /// Empty match at byte offset `offset`, if this pattern may match
+    /// nothing. `predicate` does not return such matches: the first pattern
+    /// of a rule only matches nothing right where the match of a following
+    /// pattern starts, and the rule places it there.
+    fn empty_match(&self, _offset: usize) -> Option<Self::M> {
+        None
+    }
+}
+
+/// Union of the stash indexes of several patterns, `None` if one of them
+/// may match nodes of any index.
+pub fn union_stash_indexes<Index, I>(indexes: I) -> Option<Vec<Index>>
+where
+    I: IntoIterator<Item = Option<Vec<Index>>>,
+{
+    let mut union = vec![];
+    for pattern_indexes in indexes {
+        union.extend(pattern_indexes?);
+    }
+    Some(union)
 }
 
 pub trait TerminalPattern<StashValue: NodePayload + StashIndexable>:
@@ -124,10 +305,549 @@ pub trait TerminalPattern<StashValue: NodePayload + StashIndexable>:
 {
 }
 
+pub type BoxedPattern<StashValue> = Box<dyn Pattern<StashValue, M = AnyMatch<StashValue>>>;
+
+/// Adapter erasing the match type of a pattern, see `IntoAnyPattern`.
+pub struct ErasedPattern<P> {
+    pattern: P,
+}
+
+impl<StashValue, P> Pattern<StashValue> for ErasedPattern<P>
+where
+    StashValue: NodePayload + StashIndexable,
+    P: Pattern<StashValue>,
+    P::M: IntoAnyMatch<StashValue>,
+{
+    type M = AnyMatch<StashValue>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<AnyMatch<StashValue>>> {
+        Ok(self.pattern.predicate(stash, sentence)?.into_any_matches())
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        self.pattern.stash_indexes()
+    }
+
+    fn predicate_starting_at(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+        starts: &HashSet<usize>,
+    ) -> CoreResult<PredicateMatches<AnyMatch<StashValue>>> {
+        Ok(self
+            .pattern
+            .predicate_starting_at(stash, sentence, starts)?
+            .into_any_matches())
+    }
+
+    fn empty_match(&self, offset: usize) -> Option<AnyMatch<StashValue>> {
+        self.pattern.empty_match(offset).map(|m| m.into_any_match())
+    }
+}
+
+/// Boxes a pattern with its match type erased, so patterns of different
+/// types can be put in the same sequence rule.
+pub trait IntoAnyPattern<StashValue: NodePayload + StashIndexable> {
+    fn into_any_pattern(self) -> BoxedPattern<StashValue>;
+}
+
+impl<StashValue, P> IntoAnyPattern<StashValue> for P
+where
+    StashValue: NodePayload + StashIndexable + 'static,
+    P: Pattern<StashValue> + 'static,
+    P::M: IntoAnyMatch<StashValue>,
+{
+    fn into_any_pattern(self) -> BoxedPattern<StashValue> {
+        Box::new(ErasedPattern { pattern: self })
+    }
+}
+
+/// Match of an `AnyOf` pattern, with the index of the branch that matched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnyOfMatch<StashValue: NodePayload> {
+    pub branch: usize,
+    pub match_: AnyMatch<StashValue>,
+}
+
+impl<StashValue: NodePayload> Match for AnyOfMatch<StashValue> {
+    type NV = StashValue::Payload;
+    fn byte_range(&self) -> Range {
+        self.match_.byte_range()
+    }
+
+    fn push_node_refs(&self, refs: &mut ChildrenRefs) {
+        self.match_.push_node_refs(refs)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.match_.is_empty()
+    }
+}
+
+impl<StashValue: NodePayload> IntoAnyMatch<StashValue> for AnyOfMatch<StashValue> {
+    fn into_any_match(self) -> AnyMatch<StashValue> {
+        AnyMatch::AnyOf(Box::new(self))
+    }
+}
+
+/// Pattern matching any of its branches, in one slot of a rule.
+pub struct AnyOf<StashValue: NodePayload + StashIndexable> {
+    branches: Vec<BoxedPattern<StashValue>>,
+}
+
+impl<StashValue: NodePayload + StashIndexable> AnyOf<StashValue> {
+    pub fn new(branches: Vec<BoxedPattern<StashValue>>) -> AnyOf<StashValue> {
+        AnyOf { branches }
+    }
+
+    fn collect_branches<F>(
+        &self,
+        predicate: F,
+    ) -> CoreResult<PredicateMatches<AnyOfMatch<StashValue>>>
+    where
+        F: Fn(&BoxedPattern<StashValue>) -> CoreResult<PredicateMatches<AnyMatch<StashValue>>>,
+    {
+        let mut result = PredicateMatches::with_status(ParsingStatus::Exit);
+        for (branch, pattern) in self.branches.iter().enumerate() {
+            let matches = predicate(pattern)?;
+            if matches.status.is_continue() {
+                result.status = ParsingStatus::Continue;
+            }
+            for match_ in matches.into_iter() {
+                result.push(AnyOfMatch { branch, match_ })
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue> for AnyOf<StashValue> {
+    type M = AnyOfMatch<StashValue>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        self.collect_branches(|pattern| pattern.predicate(stash, sentence))
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(self.branches.iter().map(|pattern| pattern.stash_indexes()))
+    }
+
+    fn predicate_starting_at(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+        starts: &HashSet<usize>,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        self.collect_branches(|pattern| pattern.predicate_starting_at(stash, sentence, starts))
+    }
+
+    fn empty_match(&self, offset: usize) -> Option<Self::M> {
+        self.branches
+            .iter()
+            .enumerate()
+            .filter_map(|(branch, pattern)| {
+                pattern
+                    .empty_match(offset)
+                    .map(|match_| AnyOfMatch { branch, match_ })
+            })
+            .next()
+    }
+}
+
+/// Match of an `OptionalPattern`, empty if the pattern was skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptionalMatch<M: Match> {
+    pub match_: Option<M>,
+    pub byte_range: Range,
+}
+
+impl<M: Match> Match for OptionalMatch<M> {
+    type NV = M::NV;
+    fn byte_range(&self) -> Range {
+        self.byte_range
+    }
+
+    fn push_node_refs(&self, refs: &mut ChildrenRefs) {
+        if let Some(ref m) = self.match_ {
+            m.push_node_refs(refs)
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.match_.is_none()
+    }
+}
+
+impl<StashValue, M> IntoAnyMatch<StashValue> for OptionalMatch<M>
+where
+    StashValue: NodePayload,
+    M: IntoAnyMatch<StashValue> + Match<NV = StashValue::Payload>,
+{
+    fn into_any_match(self) -> AnyMatch<StashValue> {
+        AnyMatch::Optional(Box::new(OptionalMatch {
+            match_: self.match_.map(|m| m.into_any_match()),
+            byte_range: self.byte_range,
+        }))
+    }
+}
+
+/// Match of a `RepeatedPattern`: adjacent matches of the repeated pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepeatedMatch<M: Match> {
+    pub matches: Vec<M>,
+    pub byte_range: Range,
+}
+
+impl<M: Match> Match for RepeatedMatch<M> {
+    type NV = M::NV;
+    fn byte_range(&self) -> Range {
+        self.byte_range
+    }
+
+    fn push_node_refs(&self, refs: &mut ChildrenRefs) {
+        for m in &self.matches {
+            m.push_node_refs(refs)
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+}
+
+impl<StashValue, M> IntoAnyMatch<StashValue> for RepeatedMatch<M>
+where
+    StashValue: NodePayload,
+    M: IntoAnyMatch<StashValue> + Match<NV = StashValue::Payload>,
+{
+    fn into_any_match(self) -> AnyMatch<StashValue> {
+        AnyMatch::Repeated(RepeatedMatch {
+            matches: self
+                .matches
+                .into_iter()
+                .map(|m| m.into_any_match())
+                .collect(),
+            byte_range: self.byte_range,
+        })
+    }
+}
+
+pub struct OptionalPattern<P> {
+    pattern: P,
+}
+
+/// Pattern matching `pattern`, or nothing.
+pub fn optional<P>(pattern: P) -> OptionalPattern<P> {
+    OptionalPattern { pattern }
+}
+
+impl<StashValue, P> Pattern<StashValue> for OptionalPattern<P>
+where
+    StashValue: NodePayload + StashIndexable,
+    P: Pattern<StashValue>,
+{
+    type M = OptionalMatch<P::M>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        let matches = self.pattern.predicate(stash, sentence)?;
+        Ok(optional_matches(matches, vec![]))
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        self.pattern.stash_indexes()
+    }
+
+    fn predicate_starting_at(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+        starts: &HashSet<usize>,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        let matches = self
+            .pattern
+            .predicate_starting_at(stash, sentence, starts)?;
+        let mut offsets: Vec<usize> = starts.iter().cloned().collect();
+        offsets.sort();
+        Ok(optional_matches(matches, offsets))
+    }
+
+    fn empty_match(&self, offset: usize) -> Option<Self::M> {
+        Some(OptionalMatch {
+            match_: None,
+            byte_range: Range(offset, offset),
+        })
+    }
+}
+
+fn optional_matches<M, I>(
+    matches: PredicateMatches<M>,
+    empty_offsets: I,
+) -> PredicateMatches<OptionalMatch<M>>
+where
+    M: Match,
+    I: IntoIterator<Item = usize>,
+{
+    let mut result = PredicateMatches::with_status(matches.status);
+    for m in matches.into_iter() {
+        let byte_range = m.byte_range();
+        result.push(OptionalMatch {
+            match_: Some(m),
+            byte_range,
+        })
+    }
+    for offset in empty_offsets {
+        result.push(OptionalMatch {
+            match_: None,
+            byte_range: Range(offset, offset),
+        })
+    }
+    result
+}
+
+/// Default number of chains a `RepeatedPattern` enumerates from a same
+/// start position.
+pub const DEFAULT_MAX_CHAINS: usize = 64;
+
+pub struct RepeatedPattern<P> {
+    pattern: P,
+    allow_empty: bool,
+    adjacency: AdjacencyPolicy,
+    max_chains: usize,
+}
+
+impl<P> RepeatedPattern<P> {
+    /// Replaces what may separate the repeated matches.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> RepeatedPattern<P> {
+        self.adjacency = adjacency;
+        self
+    }
+
+    /// Limits the number of chains enumerated from a same start position,
+    /// which would otherwise grow exponentially with ambiguous matches.
+    /// Chains are enumerated depth first, the first match first.
+    pub fn with_max_chains(mut self, max_chains: usize) -> RepeatedPattern<P> {
+        self.max_chains = max_chains;
+        self
+    }
+
+    /// Chains starting with one of `firsts`, continued with the matches of
+    /// `following`, plus the empty matches at `empty_offsets` if allowed.
+    fn chains<M, I>(
+        &self,
+        firsts: &PredicateMatches<M>,
+        following: &PredicateMatches<M>,
+        sentence: &str,
+        tokens: Option<&Tokens>,
+        empty_offsets: I,
+    ) -> PredicateMatches<RepeatedMatch<M>>
+    where
+        M: Match,
+        I: IntoIterator<Item = usize>,
+    {
+        let successors = Successors::new(following, tokens, &self.adjacency);
+        let mut result = PredicateMatches::with_status(firsts.status);
+        let mut remaining: HashMap<usize, usize> = HashMap::new();
+        let mut chain = vec![];
+        for m in firsts.iter() {
+            let remaining = remaining.entry(m.byte_range().0).or_insert(self.max_chains);
+            chain.push(m);
+            push_chains(&successors, sentence, &mut chain, remaining, &mut result);
+            chain.pop();
+        }
+        if self.allow_empty {
+            for offset in empty_offsets {
+                result.push(RepeatedMatch {
+                    matches: vec![],
+                    byte_range: Range(offset, offset),
+                })
+            }
+        }
+        result
+    }
+}
+
+/// Pattern matching one or several adjacent matches of `pattern`.
+pub fn one_or_more<P>(pattern: P) -> RepeatedPattern<P> {
+    RepeatedPattern {
+        pattern,
+        allow_empty: false,
+        adjacency: AdjacencyPolicy::default(),
+        max_chains: DEFAULT_MAX_CHAINS,
+    }
+}
+
+/// Pattern matching any number of adjacent matches of `pattern`.
+pub fn zero_or_more<P>(pattern: P) -> RepeatedPattern<P> {
+    RepeatedPattern {
+        pattern,
+        allow_empty: true,
+        adjacency: AdjacencyPolicy::default(),
+        max_chains: DEFAULT_MAX_CHAINS,
+    }
+}
+
+impl<StashValue, P> Pattern<StashValue> for RepeatedPattern<P>
+where
+    StashValue: NodePayload + StashIndexable,
+    P: Pattern<StashValue>,
+{
+    type M = RepeatedMatch<P::M>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        let mut matches = self.pattern.predicate(stash, sentence)?;
+        matches.matches.retain(|m| !m.is_empty());
+        Ok(self.chains(&matches, &matches, sentence, stash.tokens(), vec![]))
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        self.pattern.stash_indexes()
+    }
+
+    fn predicate_starting_at(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+        starts: &HashSet<usize>,
+    ) -> CoreResult<PredicateMatches<Self::M>> {
+        let mut firsts = self
+            .pattern
+            .predicate_starting_at(stash, sentence, starts)?;
+        firsts.matches.retain(|m| !m.is_empty());
+        // the matches which may continue a chain are looked up position by
+        // position, from the ends of the matches found so far
+        let mut following = PredicateMatches::continue_with(firsts.matches.clone());
+        let mut visited = starts.clone();
+        let mut frontier = successor_starts(&firsts, sentence, stash.tokens(), &self.adjacency);
+        frontier.retain(|start| !visited.contains(start));
+        while !frontier.is_empty() {
+            visited.extend(frontier.iter().cloned());
+            let mut next = self
+                .pattern
+                .predicate_starting_at(stash, sentence, &frontier)?;
+            next.matches.retain(|m| !m.is_empty());
+            frontier = successor_starts(&next, sentence, stash.tokens(), &self.adjacency);
+            frontier.retain(|start| !visited.contains(start));
+            following.matches.extend(next.into_iter());
+        }
+        let mut offsets: Vec<usize> = starts.iter().cloned().collect();
+        offsets.sort();
+        Ok(self.chains(&firsts, &following, sentence, stash.tokens(), offsets))
+    }
+
+    fn empty_match(&self, offset: usize) -> Option<Self::M> {
+        if self.allow_empty {
+            Some(RepeatedMatch {
+                matches: vec![],
+                byte_range: Range(offset, offset),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Pushes `chain` to `result`, then every longer chain of adjacent matches
+/// starting with it, until `remaining` chains have been pushed.
+fn push_chains<'m, M: Match>(
+    successors: &Successors<'m, M>,
+    sentence: &str,
+    chain: &mut Vec<&'m M>,
+    remaining: &mut usize,
+    result: &mut PredicateMatches<RepeatedMatch<M>>,
+) {
+    let (first, last) = match (chain.first(), chain.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return,
+    };
+    if *remaining == 0 {
+        return;
+    }
+    *remaining -= 1;
+    result.push(RepeatedMatch {
+        matches: chain.iter().map(|m| (*m).clone()).collect(),
+        byte_range: Range(first.byte_range().0, last.byte_range().1),
+    });
+    for m in successors.of(last, sentence) {
+        chain.push(m);
+        push_chains(successors, sentence, chain, remaining, result);
+        chain.pop();
+    }
+}
+
+/// Matches of `regex` in `sentence` with valid boundaries, which also
+/// satisfy `accepts`.
+fn regex_matches<StashValue, F>(
+    regex: &::regex::Regex,
+    pattern_sym: Sym,
+    boundaries_checker: &BoundariesChecker,
+    sentence: &str,
+    word_bounds: &WordBounds,
+    accepts: F,
+) -> CoreResult<PredicateMatches<Text<StashValue>>>
+where
+    StashValue: NodePayload,
+    F: Fn(Range) -> bool,
+{
+    let mut results = PredicateMatches::with_status(ParsingStatus::Continue);
+    for cap in regex.captures_iter(sentence) {
+        let full = cap.get(0).ok_or_else(|| {
+            format_err!(
+                "No capture for regexp {} in rule {:?} for sentence: {}",
+                regex,
+                pattern_sym,
+                sentence
+            )
+        })?;
+        let full_range = Range(full.start(), full.end());
+        if !boundaries_checker.check_with(sentence, full_range, word_bounds) || !accepts(full_range)
+        {
+            continue;
+        }
+        let mut groups = SmallVec::new();
+        for (ix, group) in cap.iter().enumerate() {
+            let group = group.ok_or_else(|| {
+                format_err!(
+                    "No capture for regexp {} in rule {:?}, group number {} in \
+                     capture: {}",
+                    regex,
+                    pattern_sym,
+                    ix,
+                    full.as_str()
+                )
+            })?;
+            let range = Range(group.start(), group.end());
+            groups.push(range);
+        }
+        results.push(Text {
+            groups,
+            byte_range: full_range,
+            entry: None,
+            edit_distance: None,
+            pattern_sym,
+            _phantom: SendSyncPhantomData::new(),
+        })
+    }
+
+    Ok(results.exit_if_empty())
+}
+
 pub struct TextPattern<StashValue: NodePayload + StashIndexable> {
     pattern: ::regex::Regex,
     pattern_sym: Sym,
     boundaries_checker: BoundariesChecker,
+    scan_id: Option<usize>,
     _phantom: SendSyncPhantomData<StashValue>,
 }
 
@@ -141,56 +861,48 @@ impl<StashValue: NodePayload + StashIndexable> TextPattern<StashValue> {
             pattern: regex,
             pattern_sym: sym,
             boundaries_checker,
+            scan_id: None,
             _phantom: SendSyncPhantomData::new(),
         }
     }
+
+    /// Lets the matches be shared through the text scan of the `RuleSet`,
+    /// see `RuleSetBuilder::reg`.
+    pub fn with_scan_id(mut self, scan_id: usize) -> TextPattern<StashValue> {
+        self.scan_id = Some(scan_id);
+        self
+    }
+
+    fn scan(
+        &self,
+        sentence: &str,
+        word_bounds: &WordBounds,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        regex_matches(
+            &self.pattern,
+            self.pattern_sym,
+            &self.boundaries_checker,
+            sentence,
+            word_bounds,
+            |_| true,
+        )
+    }
 }
 
 impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue> for TextPattern<StashValue> {
     type M = Text<StashValue>;
     fn predicate(
         &self,
-        _stash: &Stash<StashValue>,
+        stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<Self::M>> {
-        let mut results = PredicateMatches::with_status(ParsingStatus::Continue);
-        for cap in self.pattern.captures_iter(&sentence) {
-            let full = cap.get(0).ok_or_else(|| {
-                format_err!(
-                    "No capture for regexp {} in rule {:?} for sentence: {}",
-                    self.pattern,
-                    self.pattern_sym,
-                    sentence
-                )
-            })?;
-            let full_range = Range(full.start(), full.end());
-            if !self.boundaries_checker.check(sentence, full_range) {
-                continue;
-            }
-            let mut groups = SmallVec::new();
-            for (ix, group) in cap.iter().enumerate() {
-                let group = group.ok_or_else(|| {
-                    format_err!(
-                        "No capture for regexp {} in rule {:?}, group number {} in \
-                         capture: {}",
-                        self.pattern,
-                        self.pattern_sym,
-                        ix,
-                        full.as_str()
-                    )
-                })?;
-                let range = Range(group.start(), group.end());
-                groups.push(range);
-            }
-            results.push(Text {
-                groups,
-                byte_range: full_range,
-                pattern_sym: self.pattern_sym,
-                _phantom: SendSyncPhantomData::new(),
-            })
-        }
+        stash.text_matches(self.scan_id.map(TextCacheKey::Scan), || {
+            self.scan(sentence, stash.word_bounds())
+        })
+    }
 
-        Ok(results.exit_if_empty())
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        Some(vec![])
     }
 }
 
@@ -204,6 +916,7 @@ pub struct TextNegLHPattern<StashValue: NodePayload + StashIndexable> {
     neg_look_ahead: ::regex::Regex,
     boundaries_checker: BoundariesChecker,
     pattern_sym: Sym,
+    scan_id: Option<usize>,
     _phantom: SendSyncPhantomData<StashValue>,
 }
 
@@ -219,9 +932,37 @@ impl<StashValue: NodePayload + StashIndexable> TextNegLHPattern<StashValue> {
             neg_look_ahead,
             pattern_sym,
             boundaries_checker,
+            scan_id: None,
             _phantom: SendSyncPhantomData::new(),
         }
     }
+
+    /// Lets the matches be shared through the text scan of the `RuleSet`,
+    /// see `RuleSetBuilder::reg_neg_lh`.
+    pub fn with_scan_id(mut self, scan_id: usize) -> TextNegLHPattern<StashValue> {
+        self.scan_id = Some(scan_id);
+        self
+    }
+
+    fn scan(
+        &self,
+        sentence: &str,
+        word_bounds: &WordBounds,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        regex_matches(
+            &self.pattern,
+            self.pattern_sym,
+            &self.boundaries_checker,
+            sentence,
+            word_bounds,
+            |range| {
+                self.neg_look_ahead
+                    .find(&sentence[range.1..])
+                    .map(|mat| mat.start() != 0)
+                    .unwrap_or(true)
+            },
+        )
+    }
 }
 
 impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue>
@@ -230,57 +971,372 @@ impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue>
     type M = Text<StashValue>;
     fn predicate(
         &self,
-        _stash: &Stash<StashValue>,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        stash.text_matches(self.scan_id.map(TextCacheKey::Scan), || {
+            self.scan(sentence, stash.word_bounds())
+        })
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        Some(vec![])
+    }
+}
+
+impl<StashValue: NodePayload + StashIndexable> TerminalPattern<StashValue>
+    for TextNegLHPattern<StashValue>
+{
+}
+
+/// Direction and polarity of a `Lookaround`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LookaroundKind {
+    PositiveLookAhead,
+    NegativeLookAhead,
+    PositiveLookBehind,
+    NegativeLookBehind,
+}
+
+/// Condition on the text right after or right before a match, which is
+/// not part of the match range. The regex only sees the sentence up to the
+/// match start for look-behinds, and from the match end for look-aheads.
+#[derive(Clone, Debug)]
+pub struct Lookaround {
+    regex: ::regex::Regex,
+    kind: LookaroundKind,
+}
+
+impl Lookaround {
+    pub fn new(regex: &str, kind: LookaroundKind) -> CoreResult<Lookaround> {
+        let anchored = match kind {
+            LookaroundKind::PositiveLookAhead | LookaroundKind::NegativeLookAhead => {
+                format!("^(?:{})", regex)
+            }
+            LookaroundKind::PositiveLookBehind | LookaroundKind::NegativeLookBehind => {
+                format!("(?:{})$", regex)
+            }
+        };
+        Ok(Lookaround {
+            regex: ::regex::Regex::new(&anchored)?,
+            kind,
+        })
+    }
+
+    pub fn kind(&self) -> LookaroundKind {
+        self.kind
+    }
+
+    /// Whether a match of `sentence` on `range` satisfies the condition.
+    pub fn accepts(&self, sentence: &str, range: Range) -> bool {
+        match self.kind {
+            LookaroundKind::PositiveLookAhead => self.regex.is_match(&sentence[range.1..]),
+            LookaroundKind::NegativeLookAhead => !self.regex.is_match(&sentence[range.1..]),
+            LookaroundKind::PositiveLookBehind => self.regex.is_match(&sentence[..range.0]),
+            LookaroundKind::NegativeLookBehind => !self.regex.is_match(&sentence[..range.0]),
+        }
+    }
+}
+
+/// Text pattern whose matches must also satisfy look-around conditions.
+pub struct TextLookaroundPattern<StashValue: NodePayload + StashIndexable> {
+    pattern: ::regex::Regex,
+    lookarounds: Vec<Lookaround>,
+    boundaries_checker: BoundariesChecker,
+    pattern_sym: Sym,
+    scan_id: Option<usize>,
+    _phantom: SendSyncPhantomData<StashValue>,
+}
+
+impl<StashValue: NodePayload + StashIndexable> TextLookaroundPattern<StashValue> {
+    pub fn new(
+        pattern: ::regex::Regex,
+        lookarounds: Vec<Lookaround>,
+        pattern_sym: Sym,
+        boundaries_checker: BoundariesChecker,
+    ) -> TextLookaroundPattern<StashValue> {
+        TextLookaroundPattern {
+            pattern,
+            lookarounds,
+            pattern_sym,
+            boundaries_checker,
+            scan_id: None,
+            _phantom: SendSyncPhantomData::new(),
+        }
+    }
+
+    /// Lets the matches be shared through the text scan of the `RuleSet`,
+    /// see `RuleSetBuilder::reg_pos_lh`.
+    pub fn with_scan_id(mut self, scan_id: usize) -> TextLookaroundPattern<StashValue> {
+        self.scan_id = Some(scan_id);
+        self
+    }
+
+    fn scan(
+        &self,
         sentence: &str,
+        word_bounds: &WordBounds,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        regex_matches(
+            &self.pattern,
+            self.pattern_sym,
+            &self.boundaries_checker,
+            sentence,
+            word_bounds,
+            |range| self.lookarounds.iter().all(|l| l.accepts(sentence, range)),
+        )
+    }
+}
+
+impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue>
+    for TextLookaroundPattern<StashValue>
+{
+    type M = Text<StashValue>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        stash.text_matches(self.scan_id.map(TextCacheKey::Scan), || {
+            self.scan(sentence, stash.word_bounds())
+        })
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        Some(vec![])
+    }
+}
+
+impl<StashValue: NodePayload + StashIndexable> TerminalPattern<StashValue>
+    for TextLookaroundPattern<StashValue>
+{
+}
+
+/// Terminal pattern matching the entries of a word list, possibly
+/// overlapping. Entries are matched ignoring case, the sentence and the
+/// entries being lowercased char by char.
+pub struct GazetteerPattern<StashValue: NodePayload + StashIndexable> {
+    automaton: AhoCorasick,
+    pattern_sym: Sym,
+    boundaries_checker: BoundariesChecker,
+    cache_id: Option<usize>,
+    _phantom: SendSyncPhantomData<StashValue>,
+}
+
+impl<StashValue: NodePayload + StashIndexable> GazetteerPattern<StashValue> {
+    pub fn new<I, E>(
+        entries: I,
+        pattern_sym: Sym,
+        boundaries_checker: BoundariesChecker,
+    ) -> CoreResult<GazetteerPattern<StashValue>>
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<str>,
+    {
+        let entries: Vec<String> = entries
+            .into_iter()
+            .map(|e| e.as_ref().chars().flat_map(char::to_lowercase).collect())
+            .collect();
+        let automaton = AhoCorasick::new(&entries)?;
+        Ok(GazetteerPattern {
+            automaton,
+            pattern_sym,
+            boundaries_checker,
+            cache_id: None,
+            _phantom: SendSyncPhantomData::new(),
+        })
+    }
+
+    /// Lets the matches be cached by the stash, see
+    /// `RuleSetBuilder::gazetteer`.
+    pub fn with_cache_id(mut self, cache_id: usize) -> GazetteerPattern<StashValue> {
+        self.cache_id = Some(cache_id);
+        self
+    }
+
+    fn scan(
+        &self,
+        sentence: &str,
+        word_bounds: &WordBounds,
     ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
         let mut results = PredicateMatches::with_status(ParsingStatus::Continue);
-        for cap in self.pattern.captures_iter(&sentence) {
-            let full = cap.get(0).ok_or_else(|| {
-                format_err!(
-                    "No capture for regexp {} in rule {:?} for sentence: {}",
-                    self.pattern,
-                    self.pattern_sym,
-                    sentence
-                )
-            })?;
-            let full_range = Range(full.start(), full.end());
-            if !self.boundaries_checker.check(sentence, full_range) {
+        let (lowercased, offsets) = lowercase_with_offsets(sentence);
+        for mat in self.automaton.find_overlapping_iter(&lowercased) {
+            let range = match (offsets[mat.start()], offsets[mat.end()]) {
+                (Some(start), Some(end)) => Range(start, end),
+                _ => continue,
+            };
+            if !self
+                .boundaries_checker
+                .check_with(sentence, range, word_bounds)
+            {
                 continue;
             }
-            if let Some(mat) = self.neg_look_ahead.find(&sentence[full.end()..]) {
-                if mat.start() == 0 {
+            let mut text = Text::new(SmallVec::from_elem(range, 1), range, self.pattern_sym);
+            text.entry = Some(mat.pattern().as_usize());
+            results.push(text)
+        }
+        Ok(results.exit_if_empty())
+    }
+}
+
+impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue>
+    for GazetteerPattern<StashValue>
+{
+    type M = Text<StashValue>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        stash.text_matches(self.cache_id.map(TextCacheKey::Lexicon), || {
+            self.scan(sentence, stash.word_bounds())
+        })
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        Some(vec![])
+    }
+}
+
+impl<StashValue: NodePayload + StashIndexable> TerminalPattern<StashValue>
+    for GazetteerPattern<StashValue>
+{
+}
+
+/// Terminal pattern matching the words of the sentence within an edit
+/// distance of an entry of a lexicon, case-insensitively and with words
+/// compared space-separated. A span of words only matches its closest
+/// entry, the first one on ties.
+pub struct FuzzyPattern<StashValue: NodePayload + StashIndexable> {
+    entries: Vec<String>,
+    max_distance: usize,
+    pattern_sym: Sym,
+    boundaries_checker: BoundariesChecker,
+    cache_id: Option<usize>,
+    _phantom: SendSyncPhantomData<StashValue>,
+}
+
+impl<StashValue: NodePayload + StashIndexable> FuzzyPattern<StashValue> {
+    pub fn new<I, E>(
+        entries: I,
+        max_distance: usize,
+        pattern_sym: Sym,
+        boundaries_checker: BoundariesChecker,
+    ) -> FuzzyPattern<StashValue>
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<str>,
+    {
+        FuzzyPattern {
+            entries: entries
+                .into_iter()
+                .map(|e| {
+                    e.as_ref()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                        .to_lowercase()
+                })
+                .collect(),
+            max_distance,
+            pattern_sym,
+            boundaries_checker,
+            cache_id: None,
+            _phantom: SendSyncPhantomData::new(),
+        }
+    }
+
+    /// Lets the matches be cached by the stash, see `RuleSetBuilder::fuzzy`.
+    pub fn with_cache_id(mut self, cache_id: usize) -> FuzzyPattern<StashValue> {
+        self.cache_id = Some(cache_id);
+        self
+    }
+
+    fn scan(
+        &self,
+        sentence: &str,
+        word_bounds: &WordBounds,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        let words = word_ranges(sentence);
+        // lowercased windows of the sentence with their length in chars, by
+        // number of words
+        let mut windows: HashMap<usize, Vec<(Range, String, usize)>> = HashMap::new();
+        let mut closest: HashMap<Range, (usize, usize)> = HashMap::new();
+        for (entry, text) in self.entries.iter().enumerate() {
+            let num_words = text.split_whitespace().count().max(1);
+            let text_len = text.chars().count();
+            let candidates = windows.entry(num_words).or_insert_with(|| {
+                words
+                    .windows(num_words)
+                    .map(|span| {
+                        let candidate = span
+                            .iter()
+                            .map(|w| &sentence[w.0..w.1])
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                            .to_lowercase();
+                        let len = candidate.chars().count();
+                        (Range(span[0].0, span[num_words - 1].1), candidate, len)
+                    })
+                    .collect()
+            });
+            for &(range, ref candidate, len) in candidates.iter() {
+                // the distance is at least the difference of lengths
+                if len.max(text_len) - len.min(text_len) > self.max_distance {
                     continue;
                 }
+                let distance = levenshtein(candidate, text);
+                if distance > self.max_distance {
+                    continue;
+                }
+                let best = closest.entry(range).or_insert((entry, distance));
+                if distance < best.1 {
+                    *best = (entry, distance);
+                }
             }
-            let mut groups = SmallVec::new();
-            for (ix, group) in cap.iter().enumerate() {
-                let group = group.ok_or_else(|| {
-                    format_err!(
-                        "No capture for regexp {} in rule {:?}, group number {} in \
-                         capture: {}",
-                        self.pattern,
-                        self.pattern_sym,
-                        ix,
-                        full.as_str()
-                    )
-                })?;
-                let range = Range(group.start(), group.end());
-                groups.push(range);
+        }
+        let mut closest: Vec<(Range, usize, usize)> = closest
+            .into_iter()
+            .map(|(range, (entry, distance))| (range, entry, distance))
+            .collect();
+        closest.sort_by_key(|c| (c.0 .0, c.0 .1));
+        let mut results = PredicateMatches::with_status(ParsingStatus::Continue);
+        for (range, entry, distance) in closest {
+            if !self
+                .boundaries_checker
+                .check_with(sentence, range, word_bounds)
+            {
+                continue;
             }
-            results.push(Text {
-                groups,
-                byte_range: full_range,
-                pattern_sym: self.pattern_sym,
-                _phantom: SendSyncPhantomData::new(),
-            })
+            let mut text = Text::new(SmallVec::from_elem(range, 1), range, self.pattern_sym);
+            text.entry = Some(entry);
+            text.edit_distance = Some(distance);
+            results.push(text)
         }
-
         Ok(results.exit_if_empty())
     }
 }
 
+impl<StashValue: NodePayload + StashIndexable> Pattern<StashValue> for FuzzyPattern<StashValue> {
+    type M = Text<StashValue>;
+    fn predicate(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Text<StashValue>>> {
+        stash.text_matches(self.cache_id.map(TextCacheKey::Lexicon), || {
+            self.scan(sentence, stash.word_bounds())
+        })
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        Some(vec![])
+    }
+}
+
 impl<StashValue: NodePayload + StashIndexable> TerminalPattern<StashValue>
-    for TextNegLHPattern<StashValue>
+    for FuzzyPattern<StashValue>
 {
 }
 
@@ -291,6 +1347,7 @@ where
     V: NodePayload + InnerStashIndexable,
 {
     predicates: Vec<Box<dyn Fn(&V) -> bool + Send + Sync>>,
+    key: PredicateKey,
     _phantom: SendSyncPhantomData<V>,
 }
 
@@ -298,6 +1355,7 @@ impl<V: NodePayload + InnerStashIndexable> AnyNodePattern<V> {
     pub fn new() -> AnyNodePattern<V> {
         FilterNodePattern {
             predicates: vec![],
+            key: PredicateKey::fresh(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
@@ -310,6 +1368,7 @@ where
     pub fn filter(predicates: Vec<Box<dyn Fn(&V) -> bool + Sync + Send>>) -> FilterNodePattern<V> {
         FilterNodePattern {
             predicates,
+            key: PredicateKey::fresh(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
@@ -322,15 +1381,34 @@ where
         + InnerStashIndexable<Index = StashValue::Index>
         + AttemptFrom<StashValue>,
 {
-    type M = ParsedNode<V>;
+    type M = NodeMatch<V>;
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        Some(vec![V::index()])
+    }
+
     fn predicate(
         &self,
         stash: &Stash<StashValue>,
         _sentence: &str,
-    ) -> CoreResult<PredicateMatches<ParsedNode<V>>> {
-        Ok(PredicateMatches::continue_with(stash.filter(|v| {
-            self.predicates.iter().all(|predicate| (predicate)(&v))
-        })))
+    ) -> CoreResult<PredicateMatches<NodeMatch<V>>> {
+        Ok(PredicateMatches::continue_with(
+            stash.filter(self.key, |v| {
+                self.predicates.iter().all(|predicate| (predicate)(&v))
+            }),
+        ))
+    }
+
+    fn predicate_starting_at(
+        &self,
+        stash: &Stash<StashValue>,
+        _sentence: &str,
+        starts: &HashSet<usize>,
+    ) -> CoreResult<PredicateMatches<NodeMatch<V>>> {
+        Ok(PredicateMatches::continue_with(stash.filter_starting_at(
+            self.key,
+            starts,
+            |v| self.predicates.iter().all(|predicate| (predicate)(&v)),
+        )))
     }
 }
 
@@ -394,4 +1472,132 @@ mod tests {
             pat.predicate(&stash, "aaa-toto").unwrap().matches
         );
     }
+
+    #[test]
+    fn test_gazetteer_pattern() {
+        let stash = Stash::default();
+        let checker = BoundariesChecker::separated_alphanumeric_word();
+        let pat: GazetteerPattern<usize> =
+            GazetteerPattern::new(["new york", "york", "paris"], Sym(0), checker).unwrap();
+        let matches: Vec<_> = pat
+            .predicate(&stash, "From New York to Paris, not yorkshire")
+            .unwrap()
+            .matches
+            .into_iter()
+            .map(|t| (t.byte_range, t.entry))
+            .collect();
+        assert_eq!(
+            vec![
+                (Range(5, 13), Some(0)),
+                (Range(9, 13), Some(1)),
+                (Range(17, 22), Some(2)),
+            ],
+            matches
+        );
+        assert!(pat.predicate(&stash, "nothing").unwrap().status.is_exit());
+
+        let checker = BoundariesChecker::separated_alphanumeric_word();
+        let pat: GazetteerPattern<usize> =
+            GazetteerPattern::new(["zürich", "Février"], Sym(0), checker).unwrap();
+        let matches: Vec<_> = pat
+            .predicate(&stash, "İ ZÜRICH en février")
+            .unwrap()
+            .matches
+            .into_iter()
+            .map(|t| (t.byte_range, t.entry))
+            .collect();
+        assert_eq!(
+            vec![(Range(3, 10), Some(0)), (Range(14, 22), Some(1))],
+            matches
+        );
+    }
+
+    #[test]
+    fn test_lookaround_kinds() {
+        let sentence = "v5 at 5";
+        let five = Range(1, 2);
+        let other_five = Range(6, 7);
+        let neg_lb = Lookaround::new("[a-z]", LookaroundKind::NegativeLookBehind).unwrap();
+        assert!(!neg_lb.accepts(sentence, five));
+        assert!(neg_lb.accepts(sentence, other_five));
+        let pos_lb = Lookaround::new("at\\s+", LookaroundKind::PositiveLookBehind).unwrap();
+        assert!(!pos_lb.accepts(sentence, five));
+        assert!(pos_lb.accepts(sentence, other_five));
+        let pos_lh = Lookaround::new("\\s*at", LookaroundKind::PositiveLookAhead).unwrap();
+        assert!(pos_lh.accepts(sentence, five));
+        assert!(!pos_lh.accepts(sentence, other_five));
+        let neg_lh = Lookaround::new("\\s*at", LookaroundKind::NegativeLookAhead).unwrap();
+        assert!(!neg_lh.accepts(sentence, five));
+        assert!(neg_lh.accepts(sentence, other_five));
+    }
+
+    #[test]
+    fn test_fuzzy_pattern() {
+        let stash = Stash::default();
+        let checker = BoundariesChecker::separated_alphanumeric_word();
+        let pat: FuzzyPattern<usize> =
+            FuzzyPattern::new(["tomorrow", "new york", "tomato"], 2, Sym(0), checker);
+        let matches: Vec<_> = pat
+            .predicate(&stash, "Tomorow in New-Yrok, not today")
+            .unwrap()
+            .matches
+            .into_iter()
+            .map(|t| (t.byte_range, t.entry, t.edit_distance))
+            .collect();
+        assert_eq!(
+            vec![
+                (Range(0, 7), Some(0), Some(1)),
+                (Range(11, 19), Some(1), Some(2)),
+            ],
+            matches
+        );
+        assert!(pat.predicate(&stash, "nothing").unwrap().status.is_exit());
+    }
+
+    #[test]
+    fn test_text_scan_and_cache() {
+        let checker = BoundariesChecker::detailed();
+        let a: TextPattern<usize> =
+            TextPattern::new(::regex::Regex::new("a+").unwrap(), Sym(0), checker.clone())
+                .with_scan_id(0);
+        let b: TextPattern<usize> =
+            TextPattern::new(::regex::Regex::new("b+").unwrap(), Sym(1), checker).with_scan_id(1);
+        let mut stash = Stash::default();
+        let text_scan = ::regex::RegexSet::new(["a+", "b+"]).unwrap();
+        stash.set_text_scan(text_scan.matches("aa cc"));
+        assert!(b.predicate(&stash, "aa cc").unwrap().status.is_exit());
+
+        let scans = ::std::cell::Cell::new(0);
+        for _ in 0..3 {
+            let matches = stash
+                .text_matches(Some(TextCacheKey::Scan(0)), || {
+                    scans.set(scans.get() + 1);
+                    a.predicate(&Stash::default(), "aa cc")
+                })
+                .unwrap();
+            assert_eq!(
+                vec![Range(0, 2)],
+                matches.iter().map(|t| t.byte_range).collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(1, scans.get());
+        assert_eq!(1, a.predicate(&stash, "aa cc").unwrap().len());
+
+        // gazetteer and fuzzy matches are cached under their own ids, apart
+        // from the scan ids, so the stash of a sentence serves them back
+        // without scanning, and the text scan never rules them out
+        let checker = BoundariesChecker::separated_alphanumeric_word();
+        let gazetteer: GazetteerPattern<usize> =
+            GazetteerPattern::new(["paris"], Sym(2), checker.clone())
+                .unwrap()
+                .with_cache_id(0);
+        let fuzzy: FuzzyPattern<usize> =
+            FuzzyPattern::new(["tomorrow"], 1, Sym(3), checker).with_cache_id(1);
+        let mut stash = Stash::default();
+        stash.set_text_scan(text_scan.matches("to paris"));
+        assert_eq!(1, gazetteer.predicate(&stash, "to paris").unwrap().len());
+        assert_eq!(1, gazetteer.predicate(&stash, "nothing").unwrap().len());
+        assert_eq!(1, fuzzy.predicate(&stash, "tomorow").unwrap().len());
+        assert_eq!(1, fuzzy.predicate(&stash, "nothing").unwrap().len());
+    }
 }
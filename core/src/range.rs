@@ -10,6 +10,9 @@ impl Range {
         self.partial_cmp(other).is_none() && (self.1 >= other.0 && other.1 >= self.0)
     }
 
+    /// Walks `string` on every call, converting several ranges of a same
+    /// sentence is cheaper through an `OffsetTable`.
+    #[deprecated(since = "0.10.0", note = "use `OffsetTable::char_range` instead")]
     pub fn char_range(&self, string: &str) -> Range {
         Range(
             convert_char_index(string, self.0),
@@ -17,6 +20,9 @@ impl Range {
         )
     }
 
+    /// Walks `string` on every call, converting several ranges of a same
+    /// sentence is cheaper through an `OffsetTable`.
+    #[deprecated(since = "0.10.0", note = "use `OffsetTable::byte_range` instead")]
     pub fn byte_range(&self, string: &str) -> Range {
         Range(
             convert_byte_index(string, self.0),
@@ -73,3 +79,103 @@ pub fn convert_byte_index(string: &str, char_index: usize) -> usize {
     }
     result
 }
+
+/// Offsets of the chars of a sentence, built once to convert positions
+/// between bytes, chars and UTF-16 code units without walking the sentence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetTable {
+    byte_offsets: Vec<usize>,
+    utf16_offsets: Vec<usize>,
+}
+
+impl OffsetTable {
+    pub fn new(string: &str) -> OffsetTable {
+        let mut byte_offsets = Vec::with_capacity(string.len() + 1);
+        let mut utf16_offsets = Vec::with_capacity(string.len() + 1);
+        let mut utf16_offset = 0;
+        for (byte_offset, c) in string.char_indices() {
+            byte_offsets.push(byte_offset);
+            utf16_offsets.push(utf16_offset);
+            utf16_offset += c.len_utf16();
+        }
+        byte_offsets.push(string.len());
+        utf16_offsets.push(utf16_offset);
+        OffsetTable {
+            byte_offsets,
+            utf16_offsets,
+        }
+    }
+
+    /// Index of the char starting at `byte_index`, or of the next one if
+    /// `byte_index` falls inside a char. Same as `convert_char_index`.
+    pub fn char_index(&self, byte_index: usize) -> usize {
+        let num_chars = self.byte_offsets.len() - 1;
+        self.byte_offsets
+            .binary_search(&byte_index)
+            .unwrap_or_else(|next| next)
+            .min(num_chars)
+    }
+
+    /// Byte offset of the char at `char_index`. Same as
+    /// `convert_byte_index`.
+    pub fn byte_index(&self, char_index: usize) -> usize {
+        let num_chars = self.byte_offsets.len() - 1;
+        self.byte_offsets[char_index.min(num_chars)]
+    }
+
+    /// Offset in UTF-16 code units of the char at `byte_index`.
+    pub fn utf16_index(&self, byte_index: usize) -> usize {
+        self.utf16_offsets[self.char_index(byte_index)]
+    }
+
+    pub fn char_range(&self, byte_range: Range) -> Range {
+        Range(self.char_index(byte_range.0), self.char_index(byte_range.1))
+    }
+
+    pub fn byte_range(&self, char_range: Range) -> Range {
+        Range(self.byte_index(char_range.0), self.byte_index(char_range.1))
+    }
+
+    pub fn utf16_range(&self, byte_range: Range) -> Range {
+        Range(
+            self.utf16_index(byte_range.0),
+            self.utf16_index(byte_range.1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_table() {
+        for string in &["", "abc", "l'été à 10h", "明天 3点", "mañana 🎉 ok"] {
+            let table = OffsetTable::new(string);
+            for byte_index in 0..string.len() + 2 {
+                assert_eq!(
+                    convert_char_index(string, byte_index),
+                    table.char_index(byte_index)
+                );
+            }
+            for char_index in 0..string.chars().count() + 2 {
+                assert_eq!(
+                    convert_byte_index(string, char_index),
+                    table.byte_index(char_index)
+                );
+            }
+        }
+        let string = "mañana 🎉 ok";
+        let table = OffsetTable::new(string);
+        let party = Range(8, 12);
+        assert_eq!("🎉", &string[party.0..party.1]);
+        assert_eq!(Range(7, 8), table.char_range(party));
+        assert_eq!(party, table.byte_range(Range(7, 8)));
+        assert_eq!(Range(7, 9), table.utf16_range(party));
+        assert_eq!(Range(10, 12), table.utf16_range(Range(13, 15)));
+        assert_eq!(
+            string.encode_utf16().count(),
+            table.utf16_index(string.len())
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use crate::pattern::*;
 use crate::stash::Stash;
 use crate::{
-    CoreResult, NodePayload, ParsedNode, ParsingStatus, Range, SendSyncPhantomData, StashIndexable,
-    Sym,
+    AdjacencyPolicy, AttemptFrom, ChildrenRefs, CoreResult, ForestNode, NodePayload, ParsingStatus,
+    Range, SendSyncPhantomData, StashIndexable, Sym, Tokens,
 };
 use smallvec::SmallVec;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 #[derive(Debug, Fail)]
 pub enum RuleError {
@@ -14,11 +15,11 @@ pub enum RuleError {
 
 pub type RuleResult<T> = Result<T, failure::Error>;
 
-macro_rules! svec {
-    ($($item:expr),*) => { {
-        let mut v =SmallVec::new();
-        $( v.push($item); )*
-        v
+macro_rules! node_refs {
+    ($($match_:expr),*) => { {
+        let mut refs = ChildrenRefs::new();
+        $( $match_.push_node_refs(&mut refs); )*
+        refs
     }
     }
 }
@@ -44,47 +45,261 @@ impl<'a, V: NodePayload> RuleProductionArg<'a, Text<V>> {
     pub fn num_groups(&self) -> usize {
         self.match_.groups.len()
     }
+
+    /// Id of the matched entry, for gazetteer and fuzzy patterns.
+    pub fn entry(&self) -> Option<usize> {
+        self.match_.entry
+    }
+
+    /// Edit distance to the matched entry, for fuzzy patterns.
+    pub fn edit_distance(&self) -> Option<usize> {
+        self.match_.edit_distance
+    }
 }
 
-impl<'a, V: NodePayload> RuleProductionArg<'a, ParsedNode<V>> {
+impl<'a, V: NodePayload> RuleProductionArg<'a, NodeMatch<V>> {
     pub fn value(&self) -> &V {
         &self.match_.value
     }
 }
 
-fn adjacent<A: Match, B: Match>(a: &A, b: &B, sentence: &str) -> bool {
-    a.byte_range().1 <= b.byte_range().0
-        && sentence[a.byte_range().1..b.byte_range().0]
-            .chars()
-            .all(|c| c.is_whitespace())
+impl<'a, StashValue: NodePayload> RuleProductionArg<'a, AnyOfMatch<StashValue>> {
+    /// Index of the branch of the `AnyOf` pattern that matched.
+    pub fn branch(&self) -> usize {
+        self.match_.branch
+    }
+
+    pub fn arg(&self) -> RuleProductionArg<'a, AnyMatch<StashValue>> {
+        RuleProductionArg::new(self.sentence, &self.match_.match_)
+    }
+}
+
+impl<'a, M: Match> RuleProductionArg<'a, OptionalMatch<M>> {
+    /// Argument of the optional pattern, `None` if it was skipped.
+    pub fn get(&self) -> Option<RuleProductionArg<'a, M>> {
+        self.match_
+            .match_
+            .as_ref()
+            .map(|m| RuleProductionArg::new(self.sentence, m))
+    }
+}
+
+impl<'a, M: Match> RuleProductionArg<'a, RepeatedMatch<M>> {
+    /// Arguments of the repeated pattern, in the sentence order.
+    pub fn all(&self) -> Vec<RuleProductionArg<'a, M>> {
+        self.match_
+            .matches
+            .iter()
+            .map(|m| RuleProductionArg::new(self.sentence, m))
+            .collect()
+    }
+}
+
+impl<'a, StashValue: NodePayload> RuleProductionArg<'a, AnyMatch<StashValue>> {
+    pub fn as_text(&self) -> Option<RuleProductionArg<'a, Text<StashValue>>> {
+        match self.match_ {
+            AnyMatch::Text(text) => Some(RuleProductionArg::new(self.sentence, text)),
+            _ => None,
+        }
+    }
+
+    pub fn as_node(&self) -> Option<RuleProductionArg<'a, NodeMatch<StashValue>>> {
+        match self.match_ {
+            AnyMatch::Node(node) => Some(RuleProductionArg::new(self.sentence, node)),
+            _ => None,
+        }
+    }
+
+    pub fn as_any_of(&self) -> Option<RuleProductionArg<'a, AnyOfMatch<StashValue>>> {
+        match self.match_ {
+            AnyMatch::AnyOf(any_of) => Some(RuleProductionArg::new(self.sentence, any_of)),
+            _ => None,
+        }
+    }
+
+    pub fn as_optional(
+        &self,
+    ) -> Option<RuleProductionArg<'a, OptionalMatch<AnyMatch<StashValue>>>> {
+        match self.match_ {
+            AnyMatch::Optional(optional) => Some(RuleProductionArg::new(self.sentence, optional)),
+            _ => None,
+        }
+    }
+
+    pub fn as_repeated(
+        &self,
+    ) -> Option<RuleProductionArg<'a, RepeatedMatch<AnyMatch<StashValue>>>> {
+        match self.match_ {
+            AnyMatch::Repeated(repeated) => Some(RuleProductionArg::new(self.sentence, repeated)),
+            _ => None,
+        }
+    }
+
+    /// Value of the matched node, if this arg matched a node of type `V`.
+    pub fn attempt_value<V: AttemptFrom<StashValue>>(&self) -> Option<V> {
+        match self.match_ {
+            AnyMatch::Node(node) => V::attempt_from(node.value.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Range covered by the non-empty matches of a rule, `None` when they are
+/// all empty as such a rule produces no node.
+fn covered_range(ranges: &[Option<Range>]) -> Option<Range> {
+    let mut covered = ranges.iter().filter_map(|r| *r);
+    let first = covered.next()?;
+    Some(Range(first.0, covered.next_back().unwrap_or(first).1))
+}
+
+/// Byte offsets at which the match following `previous` may start. An empty
+/// match is already placed after the gap following the match before it, so
+/// the next match starts right where it is.
+fn next_starts<M: Match>(
+    previous: &M,
+    sentence: &str,
+    tokens: Option<&Tokens>,
+    adjacency: &AdjacencyPolicy,
+) -> SmallVec<[usize; 4]> {
+    if previous.is_empty() {
+        SmallVec::from_elem(previous.byte_range().0, 1)
+    } else {
+        adjacency.adjacent_starts(sentence, previous.byte_range().1, tokens)
+    }
+}
+
+/// Adds the start offsets of the matches of `pattern` to `starts`, and
+/// tells whether it may match nothing, the starts of the pattern after it
+/// then being needed as well.
+fn extend_starts<StashValue, P>(
+    pattern: &P,
+    stash: &Stash<StashValue>,
+    sentence: &str,
+    starts: &mut BTreeSet<usize>,
+) -> CoreResult<bool>
+where
+    StashValue: NodePayload + StashIndexable,
+    P: Pattern<StashValue> + ?Sized,
+{
+    let matches = pattern.predicate(stash, sentence)?;
+    starts.extend(matches.iter().map(|m| m.byte_range().0));
+    Ok(pattern.empty_match(0).is_some())
+}
+
+/// Matches of `pattern`, the first pattern of a rule. If it may match
+/// nothing, its empty matches are placed at the offsets `following_starts`
+/// finds, where the matches of the next patterns start: a rule starting
+/// with an empty match starts right there.
+fn first_matches<StashValue, P, F>(
+    pattern: &P,
+    stash: &Stash<StashValue>,
+    sentence: &str,
+    following_starts: F,
+) -> CoreResult<PredicateMatches<P::M>>
+where
+    StashValue: NodePayload + StashIndexable,
+    P: Pattern<StashValue> + ?Sized,
+    F: FnOnce(&mut BTreeSet<usize>) -> CoreResult<bool>,
+{
+    let mut matches = pattern.predicate(stash, sentence)?;
+    if pattern.empty_match(0).is_none() {
+        return Ok(matches);
+    }
+    let mut starts = BTreeSet::new();
+    following_starts(&mut starts)?;
+    matches.matches.extend(
+        starts
+            .into_iter()
+            .filter_map(|offset| pattern.empty_match(offset)),
+    );
+    Ok(matches)
+}
+
+pub(crate) fn successor_starts<M: Match>(
+    matches: &PredicateMatches<M>,
+    sentence: &str,
+    tokens: Option<&Tokens>,
+    adjacency: &AdjacencyPolicy,
+) -> HashSet<usize> {
+    matches
+        .iter()
+        .flat_map(|m| next_starts(m, sentence, tokens, adjacency))
+        .collect()
+}
+
+/// Matches of a pattern indexed by their start offset, to enumerate the
+/// successors of a match without scanning all of them.
+pub(crate) struct Successors<'m, M: Match> {
+    matches: &'m [M],
+    by_start: HashMap<usize, SmallVec<[usize; 2]>>,
+    tokens: Option<&'m Tokens>,
+    adjacency: &'m AdjacencyPolicy,
+}
+
+impl<'m, M: Match> Successors<'m, M> {
+    pub(crate) fn new(
+        matches: &'m PredicateMatches<M>,
+        tokens: Option<&'m Tokens>,
+        adjacency: &'m AdjacencyPolicy,
+    ) -> Successors<'m, M> {
+        let mut by_start: HashMap<usize, SmallVec<[usize; 2]>> = HashMap::new();
+        for (position, m) in matches.iter().enumerate() {
+            by_start.entry(m.byte_range().0).or_default().push(position);
+        }
+        Successors {
+            matches: &matches.matches,
+            by_start,
+            tokens,
+            adjacency,
+        }
+    }
+
+    /// Matches adjacent to `previous`, in the order of the pattern matches.
+    pub(crate) fn of<P: Match>(&self, previous: &P, sentence: &str) -> SmallVec<[&'m M; 4]> {
+        let mut positions: SmallVec<[usize; 4]> =
+            next_starts(previous, sentence, self.tokens, self.adjacency)
+                .into_iter()
+                .filter_map(|start| self.by_start.get(&start))
+                .flat_map(|positions| positions.iter().cloned())
+                .collect();
+        positions.sort();
+        positions.into_iter().map(|p| &self.matches[p]).collect()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RuleOutput<StashValue: NodePayload> {
-    pub nodes: ParsedNodes<StashValue>,
+    pub nodes: ForestNodes<StashValue>,
     pub status: ParsingStatus,
+    /// Number of matches dropped as their node was already in the stash
+    pub duplicates: usize,
 }
 
 impl<StashValue: NodePayload> RuleOutput<StashValue> {
     fn exit() -> RuleOutput<StashValue> {
         RuleOutput {
-            nodes: ParsedNodes::new(),
+            nodes: ForestNodes::new(),
             status: ParsingStatus::Exit,
+            duplicates: 0,
         }
     }
 
-    fn continue_with(nodes: ParsedNodes<StashValue>) -> RuleOutput<StashValue> {
+    fn continue_with(nodes: ForestNodes<StashValue>, duplicates: usize) -> RuleOutput<StashValue> {
         RuleOutput {
             nodes,
             status: ParsingStatus::Continue,
+            duplicates,
         }
     }
 }
 
-type ParsedNodes<StashValue> = SmallVec<[ParsedNode<StashValue>; 1]>;
+pub type ForestNodes<StashValue> = SmallVec<[ForestNode<StashValue>; 1]>;
 
 pub trait Rule<StashValue: NodePayload + StashIndexable>: Send + Sync {
     fn rule_sym(&self) -> Sym;
+    /// Indexes of the stash nodes the patterns of this rule may consume,
+    /// `None` if they may consume nodes of any index.
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>>;
     fn apply(
         &self,
         stash: &Stash<StashValue>,
@@ -104,6 +319,7 @@ where
     sym: Sym,
     pattern: PA,
     production: F,
+    adjacency: AdjacencyPolicy,
     _phantom: SendSyncPhantomData<(V, StashValue)>,
 }
 
@@ -118,6 +334,10 @@ where
         self.sym
     }
 
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        self.pattern.stash_indexes()
+    }
+
     fn apply(
         &self,
         stash: &Stash<StashValue>,
@@ -129,22 +349,25 @@ where
             return Ok(RuleOutput::exit());
         }
 
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
         let nodes: CoreResult<_> = matches
             .iter()
             .filter_map(|sub| {
-                let nodes = svec![sub.to_node()];
-                if stash.iter().all(|old_node| {
-                    old_node.root_node.children != nodes || old_node.root_node.rule_sym != self.sym
-                }) {
+                let byte_range = sub.covered_range()?;
+                let children = node_refs!(sub);
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
                     match (self.production)(&RuleProductionArg::new(sentence, sub)) {
                         Ok(v) => {
                             let payload = v.extract_payload();
-                            Some(Ok(ParsedNode::new(
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
                                 self.sym,
                                 v.into(),
-                                sub.byte_range(),
+                                byte_range,
                                 payload,
-                                nodes,
+                                children,
+                                height,
                             )))
                         }
                         Err(e) => match e.downcast::<RuleError>() {
@@ -153,11 +376,12 @@ where
                         },
                     }
                 } else {
+                    duplicates += 1;
                     None
                 }
             })
             .collect();
-        Ok(RuleOutput::continue_with(nodes?))
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
     }
 }
 
@@ -182,10 +406,17 @@ where
             sym,
             pattern: pat,
             production: prod,
+            adjacency: AdjacencyPolicy::default(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
 
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
     fn matches(
         &self,
         stash: &Stash<StashValue>,
@@ -206,6 +437,7 @@ where
     sym: Sym,
     pattern: (PA, PB),
     production: F,
+    adjacency: AdjacencyPolicy,
     _phantom: SendSyncPhantomData<(V, StashValue)>,
 }
 
@@ -223,6 +455,13 @@ where
         self.sym
     }
 
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(vec![
+            self.pattern.0.stash_indexes(),
+            self.pattern.1.stash_indexes(),
+        ])
+    }
+
     fn apply(
         &self,
         stash: &Stash<StashValue>,
@@ -234,26 +473,28 @@ where
             return Ok(RuleOutput::exit());
         }
 
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
         let nodes: CoreResult<_> = matches
             .iter()
             .filter_map(|sub| {
-                let nodes = svec![sub.0.to_node(), sub.1.to_node()];
-                if stash.iter().all(|old_node| {
-                    old_node.root_node.children != nodes || old_node.root_node.rule_sym != self.sym
-                }) {
-                    let byte_range = Range(sub.0.byte_range().0, sub.1.byte_range().1);
+                let byte_range = covered_range(&[sub.0.covered_range(), sub.1.covered_range()])?;
+                let children = node_refs!(sub.0, sub.1);
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
                     match (self.production)(
                         &RuleProductionArg::new(sentence, &sub.0),
                         &RuleProductionArg::new(sentence, &sub.1),
                     ) {
                         Ok(v) => {
                             let payload = v.extract_payload();
-                            Some(Ok(ParsedNode::new(
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
                                 self.sym,
                                 v.into(),
                                 byte_range,
                                 payload,
-                                nodes,
+                                children,
+                                height,
                             )))
                         }
                         Err(e) => match e.downcast::<RuleError>() {
@@ -262,11 +503,12 @@ where
                         },
                     }
                 } else {
+                    duplicates += 1;
                     None
                 }
             })
             .collect();
-        Ok(RuleOutput::continue_with(nodes?))
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
     }
 }
 
@@ -297,26 +539,41 @@ where
             sym,
             pattern: pat,
             production: prod,
+            adjacency: AdjacencyPolicy::default(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
 
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
     fn matches(
         &self,
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<(PA::M, PB::M)>> {
-        let matches_0 = self.pattern.0.predicate(stash, sentence)?;
+        let matches_0 = first_matches(&self.pattern.0, stash, sentence, |starts| {
+            extend_starts(&self.pattern.1, stash, sentence, starts)
+        })?;
         if matches_0.is_empty() {
             return Ok(PredicateMatches::with_status(matches_0.status));
         }
+        let matches_1 = self.pattern.1.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_0, sentence, stash.tokens(), &self.adjacency),
+        )?;
+        if matches_1.is_empty() {
+            return Ok(PredicateMatches::with_status(matches_1.status));
+        }
+        let successors_1 = Successors::new(&matches_1, stash.tokens(), &self.adjacency);
         let mut result = PredicateMatches::with_status(ParsingStatus::Continue);
-        let matches_1 = self.pattern.1.predicate(stash, sentence)?;
         for m0 in matches_0.iter() {
-            for m1 in matches_1.iter() {
-                if adjacent(m0, m1, sentence) {
-                    result.push((m0.clone(), m1.clone()))
-                }
+            for m1 in successors_1.of(m0, sentence) {
+                result.push((m0.clone(), m1.clone()))
             }
         }
         Ok(result)
@@ -341,6 +598,7 @@ where
     sym: Sym,
     pattern: (PA, PB, PC),
     production: F,
+    adjacency: AdjacencyPolicy,
     _phantom: SendSyncPhantomData<(V, StashValue)>,
 }
 
@@ -363,6 +621,14 @@ where
         self.sym
     }
 
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(vec![
+            self.pattern.0.stash_indexes(),
+            self.pattern.1.stash_indexes(),
+            self.pattern.2.stash_indexes(),
+        ])
+    }
+
     fn apply(
         &self,
         stash: &Stash<StashValue>,
@@ -374,14 +640,18 @@ where
             return Ok(RuleOutput::exit());
         }
 
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
         let nodes: CoreResult<_> = matches
             .iter()
             .filter_map(|sub| {
-                let nodes = svec!(sub.0.to_node(), sub.1.to_node(), sub.2.to_node());
-                if stash.iter().all(|old_node| {
-                    old_node.root_node.children != nodes || old_node.root_node.rule_sym != self.sym
-                }) {
-                    let byte_range = Range(sub.0.byte_range().0, sub.2.byte_range().1);
+                let byte_range = covered_range(&[
+                    sub.0.covered_range(),
+                    sub.1.covered_range(),
+                    sub.2.covered_range(),
+                ])?;
+                let children = node_refs!(sub.0, sub.1, sub.2);
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
                     match (self.production)(
                         &RuleProductionArg::new(sentence, &sub.0),
                         &RuleProductionArg::new(sentence, &sub.1),
@@ -389,12 +659,14 @@ where
                     ) {
                         Ok(v) => {
                             let payload = v.extract_payload();
-                            Some(Ok(ParsedNode::new(
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
                                 self.sym,
                                 v.clone().into(),
                                 byte_range,
                                 payload,
-                                nodes,
+                                children,
+                                height,
                             )))
                         }
                         Err(e) => match e.downcast::<RuleError>() {
@@ -403,11 +675,12 @@ where
                         },
                     }
                 } else {
+                    duplicates += 1;
                     None
                 }
             })
             .collect();
-        Ok(RuleOutput::continue_with(nodes?))
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
     }
 }
 
@@ -431,36 +704,52 @@ where
             sym,
             pattern: pat,
             production: prod,
+            adjacency: AdjacencyPolicy::default(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
 
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
     fn matches(
         &self,
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<(PA::M, PB::M, PC::M)>> {
-        let matches_0 = self.pattern.0.predicate(stash, sentence)?;
+        let matches_0 = first_matches(&self.pattern.0, stash, sentence, |starts| {
+            Ok(extend_starts(&self.pattern.1, stash, sentence, starts)?
+                && extend_starts(&self.pattern.2, stash, sentence, starts)?)
+        })?;
         if matches_0.is_empty() {
             return Ok(PredicateMatches::with_status(matches_0.status));
         }
-        let matches_1 = self.pattern.1.predicate(stash, sentence)?;
+        let matches_1 = self.pattern.1.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_0, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_1.is_empty() {
             return Ok(PredicateMatches::with_status(matches_1.status));
         }
-        let matches_2 = self.pattern.2.predicate(stash, sentence)?;
+        let successors_1 = Successors::new(&matches_1, stash.tokens(), &self.adjacency);
+        let matches_2 = self.pattern.2.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_1, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_2.is_empty() {
             return Ok(PredicateMatches::with_status(matches_2.status));
         }
+        let successors_2 = Successors::new(&matches_2, stash.tokens(), &self.adjacency);
         let mut result = PredicateMatches::with_status(ParsingStatus::Continue);
         for m0 in matches_0.iter() {
-            for m1 in matches_1.iter() {
-                if adjacent(m0, m1, sentence) {
-                    for m2 in matches_2.iter() {
-                        if adjacent(m1, m2, sentence) {
-                            result.push((m0.clone(), m1.clone(), m2.clone()))
-                        }
-                    }
+            for m1 in successors_1.of(m0, sentence) {
+                for m2 in successors_2.of(m1, sentence) {
+                    result.push((m0.clone(), m1.clone(), m2.clone()))
                 }
             }
         }
@@ -488,6 +777,7 @@ where
     sym: Sym,
     pattern: (PA, PB, PC, PD),
     production: F,
+    adjacency: AdjacencyPolicy,
     _phantom: SendSyncPhantomData<(V, StashValue)>,
 }
 
@@ -512,6 +802,15 @@ where
         self.sym
     }
 
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(vec![
+            self.pattern.0.stash_indexes(),
+            self.pattern.1.stash_indexes(),
+            self.pattern.2.stash_indexes(),
+            self.pattern.3.stash_indexes(),
+        ])
+    }
+
     fn apply(
         &self,
         stash: &Stash<StashValue>,
@@ -523,19 +822,19 @@ where
             return Ok(RuleOutput::exit());
         }
 
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
         let nodes: CoreResult<_> = matches
             .iter()
             .filter_map(|sub| {
-                let nodes = svec!(
-                    sub.0.to_node(),
-                    sub.1.to_node(),
-                    sub.2.to_node(),
-                    sub.3.to_node()
-                );
-                if stash.iter().all(|old_node| {
-                    old_node.root_node.children != nodes || old_node.root_node.rule_sym != self.sym
-                }) {
-                    let byte_range = Range(sub.0.byte_range().0, sub.3.byte_range().1);
+                let byte_range = covered_range(&[
+                    sub.0.covered_range(),
+                    sub.1.covered_range(),
+                    sub.2.covered_range(),
+                    sub.3.covered_range(),
+                ])?;
+                let children = node_refs!(sub.0, sub.1, sub.2, sub.3);
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
                     match (self.production)(
                         &RuleProductionArg::new(sentence, &sub.0),
                         &RuleProductionArg::new(sentence, &sub.1),
@@ -544,12 +843,14 @@ where
                     ) {
                         Ok(v) => {
                             let payload = v.extract_payload();
-                            Some(Ok(ParsedNode::new(
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
                                 self.sym,
                                 v.clone().into(),
                                 byte_range,
                                 payload,
-                                nodes,
+                                children,
+                                height,
                             )))
                         }
                         Err(e) => match e.downcast::<RuleError>() {
@@ -558,12 +859,12 @@ where
                         },
                     }
                 } else {
+                    duplicates += 1;
                     None
                 }
             })
             .collect();
-
-        Ok(RuleOutput::continue_with(nodes?))
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
     }
 }
 
@@ -593,43 +894,63 @@ where
             sym,
             pattern: pat,
             production: prod,
+            adjacency: AdjacencyPolicy::default(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
 
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
     fn matches(
         &self,
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<(PA::M, PB::M, PC::M, PD::M)>> {
-        let matches_0 = self.pattern.0.predicate(stash, sentence)?;
+        let matches_0 = first_matches(&self.pattern.0, stash, sentence, |starts| {
+            Ok(extend_starts(&self.pattern.1, stash, sentence, starts)?
+                && extend_starts(&self.pattern.2, stash, sentence, starts)?
+                && extend_starts(&self.pattern.3, stash, sentence, starts)?)
+        })?;
         if matches_0.is_empty() {
             return Ok(PredicateMatches::with_status(matches_0.status));
         }
-        let matches_1 = self.pattern.1.predicate(stash, sentence)?;
+        let matches_1 = self.pattern.1.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_0, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_1.is_empty() {
             return Ok(PredicateMatches::with_status(matches_1.status));
         }
-        let matches_2 = self.pattern.2.predicate(stash, sentence)?;
+        let successors_1 = Successors::new(&matches_1, stash.tokens(), &self.adjacency);
+        let matches_2 = self.pattern.2.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_1, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_2.is_empty() {
             return Ok(PredicateMatches::with_status(matches_2.status));
         }
-        let matches_3 = self.pattern.3.predicate(stash, sentence)?;
+        let successors_2 = Successors::new(&matches_2, stash.tokens(), &self.adjacency);
+        let matches_3 = self.pattern.3.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_2, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_3.is_empty() {
             return Ok(PredicateMatches::with_status(matches_3.status));
         }
+        let successors_3 = Successors::new(&matches_3, stash.tokens(), &self.adjacency);
         let mut result = PredicateMatches::with_status(ParsingStatus::Continue);
         for m0 in matches_0.iter() {
-            for m1 in matches_1.iter() {
-                if adjacent(m0, m1, sentence) {
-                    for m2 in matches_2.iter() {
-                        if adjacent(m1, m2, sentence) {
-                            for m3 in matches_3.iter() {
-                                if adjacent(m2, m3, sentence) {
-                                    result.push((m0.clone(), m1.clone(), m2.clone(), m3.clone()))
-                                }
-                            }
-                        }
+            for m1 in successors_1.of(m0, sentence) {
+                for m2 in successors_2.of(m1, sentence) {
+                    for m3 in successors_3.of(m2, sentence) {
+                        result.push((m0.clone(), m1.clone(), m2.clone(), m3.clone()))
                     }
                 }
             }
@@ -660,6 +981,7 @@ where
     sym: Sym,
     pattern: (PA, PB, PC, PD, PE),
     production: F,
+    adjacency: AdjacencyPolicy,
     _phantom: SendSyncPhantomData<(V, StashValue)>,
 }
 
@@ -687,6 +1009,16 @@ where
         self.sym
     }
 
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(vec![
+            self.pattern.0.stash_indexes(),
+            self.pattern.1.stash_indexes(),
+            self.pattern.2.stash_indexes(),
+            self.pattern.3.stash_indexes(),
+            self.pattern.4.stash_indexes(),
+        ])
+    }
+
     fn apply(
         &self,
         stash: &Stash<StashValue>,
@@ -698,20 +1030,20 @@ where
             return Ok(RuleOutput::exit());
         }
 
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
         let nodes: CoreResult<_> = matches
             .iter()
             .filter_map(|sub| {
-                let nodes = svec!(
-                    sub.0.to_node(),
-                    sub.1.to_node(),
-                    sub.2.to_node(),
-                    sub.3.to_node(),
-                    sub.4.to_node()
-                );
-                if stash.iter().all(|old_node| {
-                    old_node.root_node.children != nodes || old_node.root_node.rule_sym != self.sym
-                }) {
-                    let byte_range = Range(sub.0.byte_range().0, sub.4.byte_range().1);
+                let byte_range = covered_range(&[
+                    sub.0.covered_range(),
+                    sub.1.covered_range(),
+                    sub.2.covered_range(),
+                    sub.3.covered_range(),
+                    sub.4.covered_range(),
+                ])?;
+                let children = node_refs!(sub.0, sub.1, sub.2, sub.3, sub.4);
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
                     match (self.production)(
                         &RuleProductionArg::new(sentence, &sub.0),
                         &RuleProductionArg::new(sentence, &sub.1),
@@ -721,12 +1053,14 @@ where
                     ) {
                         Ok(v) => {
                             let payload = v.extract_payload();
-                            Some(Ok(ParsedNode::new(
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
                                 self.sym,
                                 v.into(),
                                 byte_range,
                                 payload,
-                                nodes,
+                                children,
+                                height,
                             )))
                         }
                         Err(e) => match e.downcast::<RuleError>() {
@@ -735,11 +1069,12 @@ where
                         },
                     }
                 } else {
+                    duplicates += 1;
                     None
                 }
             })
             .collect();
-        Ok(RuleOutput::continue_with(nodes?))
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
     }
 }
 
@@ -771,56 +1106,80 @@ where
             sym,
             pattern: pat,
             production: prod,
+            adjacency: AdjacencyPolicy::default(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
 
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
     fn matches(
         &self,
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<(PA::M, PB::M, PC::M, PD::M, PE::M)>> {
-        let matches_0 = self.pattern.0.predicate(stash, sentence)?;
+        let matches_0 = first_matches(&self.pattern.0, stash, sentence, |starts| {
+            Ok(extend_starts(&self.pattern.1, stash, sentence, starts)?
+                && extend_starts(&self.pattern.2, stash, sentence, starts)?
+                && extend_starts(&self.pattern.3, stash, sentence, starts)?
+                && extend_starts(&self.pattern.4, stash, sentence, starts)?)
+        })?;
         if matches_0.is_empty() {
             return Ok(PredicateMatches::with_status(matches_0.status));
         }
-        let matches_1 = self.pattern.1.predicate(stash, sentence)?;
+        let matches_1 = self.pattern.1.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_0, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_1.is_empty() {
             return Ok(PredicateMatches::with_status(matches_1.status));
         }
-        let matches_2 = self.pattern.2.predicate(stash, sentence)?;
+        let successors_1 = Successors::new(&matches_1, stash.tokens(), &self.adjacency);
+        let matches_2 = self.pattern.2.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_1, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_2.is_empty() {
             return Ok(PredicateMatches::with_status(matches_2.status));
         }
-        let matches_3 = self.pattern.3.predicate(stash, sentence)?;
+        let successors_2 = Successors::new(&matches_2, stash.tokens(), &self.adjacency);
+        let matches_3 = self.pattern.3.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_2, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_3.is_empty() {
-            return Ok(PredicateMatches::with_status(matches_3.status));;
+            return Ok(PredicateMatches::with_status(matches_3.status));
         }
-        let matches_4 = self.pattern.4.predicate(stash, sentence)?;
+        let successors_3 = Successors::new(&matches_3, stash.tokens(), &self.adjacency);
+        let matches_4 = self.pattern.4.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_3, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_4.is_empty() {
             return Ok(PredicateMatches::with_status(matches_4.status));
         }
+        let successors_4 = Successors::new(&matches_4, stash.tokens(), &self.adjacency);
         let mut result = PredicateMatches::with_status(ParsingStatus::Continue);
         for m0 in matches_0.iter() {
-            for m1 in matches_1.iter() {
-                if adjacent(m0, m1, sentence) {
-                    for m2 in matches_2.iter() {
-                        if adjacent(m1, m2, sentence) {
-                            for m3 in matches_3.iter() {
-                                if adjacent(m2, m3, sentence) {
-                                    for m4 in matches_4.iter() {
-                                        if adjacent(m3, m4, sentence) {
-                                            result.push((
-                                                m0.clone(),
-                                                m1.clone(),
-                                                m2.clone(),
-                                                m3.clone(),
-                                                m4.clone(),
-                                            ))
-                                        }
-                                    }
-                                }
-                            }
+            for m1 in successors_1.of(m0, sentence) {
+                for m2 in successors_2.of(m1, sentence) {
+                    for m3 in successors_3.of(m2, sentence) {
+                        for m4 in successors_4.of(m3, sentence) {
+                            result.push((
+                                m0.clone(),
+                                m1.clone(),
+                                m2.clone(),
+                                m3.clone(),
+                                m4.clone(),
+                            ))
                         }
                     }
                 }
@@ -854,6 +1213,7 @@ where
     sym: Sym,
     pattern: (PA, PB, PC, PD, PE, PF),
     production: F,
+    adjacency: AdjacencyPolicy,
     _phantom: SendSyncPhantomData<(V, StashValue)>,
 }
 
@@ -883,32 +1243,43 @@ where
         self.sym
     }
 
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(vec![
+            self.pattern.0.stash_indexes(),
+            self.pattern.1.stash_indexes(),
+            self.pattern.2.stash_indexes(),
+            self.pattern.3.stash_indexes(),
+            self.pattern.4.stash_indexes(),
+            self.pattern.5.stash_indexes(),
+        ])
+    }
+
     fn apply(
         &self,
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<RuleOutput<StashValue>> {
-        let matches = self.matches(&stash, sentence)?;
+        let matches = self.matches(stash, sentence)?;
 
         if matches.status.is_exit() {
             return Ok(RuleOutput::exit());
         }
 
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
         let nodes: CoreResult<_> = matches
             .iter()
             .filter_map(|sub| {
-                let nodes = svec!(
-                    sub.0.to_node(),
-                    sub.1.to_node(),
-                    sub.2.to_node(),
-                    sub.3.to_node(),
-                    sub.4.to_node(),
-                    sub.5.to_node()
-                );
-                if stash.iter().all(|old_node| {
-                    old_node.root_node.children != nodes || old_node.root_node.rule_sym != self.sym
-                }) {
-                    let byte_range = Range(sub.0.byte_range().0, sub.5.byte_range().1);
+                let byte_range = covered_range(&[
+                    sub.0.covered_range(),
+                    sub.1.covered_range(),
+                    sub.2.covered_range(),
+                    sub.3.covered_range(),
+                    sub.4.covered_range(),
+                    sub.5.covered_range(),
+                ])?;
+                let children = node_refs!(sub.0, sub.1, sub.2, sub.3, sub.4, sub.5);
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
                     match (self.production)(
                         &RuleProductionArg::new(sentence, &sub.0),
                         &RuleProductionArg::new(sentence, &sub.1),
@@ -919,12 +1290,14 @@ where
                     ) {
                         Ok(v) => {
                             let payload = v.extract_payload();
-                            Some(Ok(ParsedNode::new(
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
                                 self.sym,
                                 v.clone().into(),
                                 byte_range,
                                 payload,
-                                nodes,
+                                children,
+                                height,
                             )))
                         }
                         Err(e) => match e.downcast::<RuleError>() {
@@ -933,11 +1306,12 @@ where
                         },
                     }
                 } else {
+                    duplicates += 1;
                     None
                 }
             })
             .collect();
-        Ok(RuleOutput::continue_with(nodes?))
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
     }
 }
 
@@ -971,64 +1345,92 @@ where
             sym,
             pattern: pat,
             production: prod,
+            adjacency: AdjacencyPolicy::default(),
             _phantom: SendSyncPhantomData::new(),
         }
     }
 
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
     fn matches(
         &self,
         stash: &Stash<StashValue>,
         sentence: &str,
     ) -> CoreResult<PredicateMatches<(PA::M, PB::M, PC::M, PD::M, PE::M, PF::M)>> {
-        let matches_0 = self.pattern.0.predicate(stash, sentence)?;
+        let matches_0 = first_matches(&self.pattern.0, stash, sentence, |starts| {
+            Ok(extend_starts(&self.pattern.1, stash, sentence, starts)?
+                && extend_starts(&self.pattern.2, stash, sentence, starts)?
+                && extend_starts(&self.pattern.3, stash, sentence, starts)?
+                && extend_starts(&self.pattern.4, stash, sentence, starts)?
+                && extend_starts(&self.pattern.5, stash, sentence, starts)?)
+        })?;
         if matches_0.is_empty() {
             return Ok(PredicateMatches::with_status(matches_0.status));
         }
-        let matches_1 = self.pattern.1.predicate(stash, sentence)?;
+        let matches_1 = self.pattern.1.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_0, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_1.is_empty() {
             return Ok(PredicateMatches::with_status(matches_1.status));
         }
-        let matches_2 = self.pattern.2.predicate(stash, sentence)?;
+        let successors_1 = Successors::new(&matches_1, stash.tokens(), &self.adjacency);
+        let matches_2 = self.pattern.2.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_1, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_2.is_empty() {
             return Ok(PredicateMatches::with_status(matches_2.status));
         }
-        let matches_3 = self.pattern.3.predicate(stash, sentence)?;
+        let successors_2 = Successors::new(&matches_2, stash.tokens(), &self.adjacency);
+        let matches_3 = self.pattern.3.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_2, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_3.is_empty() {
             return Ok(PredicateMatches::with_status(matches_3.status));
         }
-        let matches_4 = self.pattern.4.predicate(stash, sentence)?;
+        let successors_3 = Successors::new(&matches_3, stash.tokens(), &self.adjacency);
+        let matches_4 = self.pattern.4.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_3, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_4.is_empty() {
             return Ok(PredicateMatches::with_status(matches_4.status));
         }
-        let matches_5 = self.pattern.5.predicate(stash, sentence)?;
+        let successors_4 = Successors::new(&matches_4, stash.tokens(), &self.adjacency);
+        let matches_5 = self.pattern.5.predicate_starting_at(
+            stash,
+            sentence,
+            &successor_starts(&matches_4, sentence, stash.tokens(), &self.adjacency),
+        )?;
         if matches_5.is_empty() {
             return Ok(PredicateMatches::with_status(matches_5.status));
         }
+        let successors_5 = Successors::new(&matches_5, stash.tokens(), &self.adjacency);
         let mut result = PredicateMatches::with_status(ParsingStatus::Continue);
         for m0 in matches_0.iter() {
-            for m1 in matches_1.iter() {
-                if adjacent(m0, m1, sentence) {
-                    for m2 in matches_2.iter() {
-                        if adjacent(m1, m2, sentence) {
-                            for m3 in matches_3.iter() {
-                                if adjacent(m2, m3, sentence) {
-                                    for m4 in matches_4.iter() {
-                                        if adjacent(m3, m4, sentence) {
-                                            for m5 in matches_5.iter() {
-                                                if adjacent(m4, m5, sentence) {
-                                                    result.push((
-                                                        m0.clone(),
-                                                        m1.clone(),
-                                                        m2.clone(),
-                                                        m3.clone(),
-                                                        m4.clone(),
-                                                        m5.clone(),
-                                                    ))
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
+            for m1 in successors_1.of(m0, sentence) {
+                for m2 in successors_2.of(m1, sentence) {
+                    for m3 in successors_3.of(m2, sentence) {
+                        for m4 in successors_4.of(m3, sentence) {
+                            for m5 in successors_5.of(m4, sentence) {
+                                result.push((
+                                    m0.clone(),
+                                    m1.clone(),
+                                    m2.clone(),
+                                    m3.clone(),
+                                    m4.clone(),
+                                    m5.clone(),
+                                ))
                             }
                         }
                     }
@@ -1039,15 +1441,193 @@ where
     }
 }
 
+/// Pushes to `result` every sequence extending `sequence` with adjacent
+/// matches of the remaining `successors`.
+fn extend_sequences<'m, M: Match>(
+    successors: &[Successors<'m, M>],
+    sentence: &str,
+    sequence: &mut Vec<&'m M>,
+    result: &mut PredicateMatches<Vec<M>>,
+) {
+    match (successors.split_first(), sequence.last().cloned()) {
+        (Some((next, rest)), Some(previous)) => {
+            for m in next.of(previous, sentence) {
+                sequence.push(m);
+                extend_sequences(rest, sentence, sequence, result);
+                sequence.pop();
+            }
+        }
+        _ => result.push(sequence.iter().map(|m| (*m).clone()).collect()),
+    }
+}
+
+/// Rule made of any number of patterns, whose production receives the
+/// arguments of all of them. Patterns are type-erased with `IntoAnyPattern`.
+pub struct SequenceRule<V, StashValue, F>
+where
+    V: NodePayload,
+    StashValue: NodePayload<Payload = V::Payload> + StashIndexable + From<V>,
+    F: for<'a> Fn(&[RuleProductionArg<'a, AnyMatch<StashValue>>]) -> RuleResult<V>,
+{
+    sym: Sym,
+    patterns: Vec<BoxedPattern<StashValue>>,
+    production: F,
+    adjacency: AdjacencyPolicy,
+    _phantom: SendSyncPhantomData<(V, StashValue)>,
+}
+
+impl<V, StashValue, F> Rule<StashValue> for SequenceRule<V, StashValue, F>
+where
+    V: NodePayload,
+    StashValue: NodePayload<Payload = V::Payload> + StashIndexable + From<V>,
+    F: for<'a> Fn(&[RuleProductionArg<'a, AnyMatch<StashValue>>]) -> RuleResult<V> + Send + Sync,
+{
+    fn rule_sym(&self) -> Sym {
+        self.sym
+    }
+
+    fn stash_indexes(&self) -> Option<Vec<StashValue::Index>> {
+        union_stash_indexes(self.patterns.iter().map(|p| p.stash_indexes()))
+    }
+
+    fn apply(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<RuleOutput<StashValue>> {
+        let matches = self.matches(stash, sentence)?;
+
+        if matches.status.is_exit() {
+            return Ok(RuleOutput::exit());
+        }
+
+        let mut duplicates = 0;
+        let mut produced = HashSet::new();
+        let nodes: CoreResult<_> = matches
+            .iter()
+            .filter_map(|sub| {
+                let ranges: SmallVec<[Option<Range>; 8]> =
+                    sub.iter().map(|m| m.covered_range()).collect();
+                let byte_range = covered_range(&ranges)?;
+                let mut children = ChildrenRefs::new();
+                for m in sub.iter() {
+                    m.push_node_refs(&mut children);
+                }
+                if !stash.has_derivation(self.sym, &children) && produced.insert(children.clone()) {
+                    let args: Vec<_> = sub
+                        .iter()
+                        .map(|m| RuleProductionArg::new(sentence, m))
+                        .collect();
+                    match (self.production)(&args) {
+                        Ok(v) => {
+                            let payload = v.extract_payload();
+                            let height = stash.height(&children);
+                            Some(Ok(ForestNode::new(
+                                self.sym,
+                                v.into(),
+                                byte_range,
+                                payload,
+                                children,
+                                height,
+                            )))
+                        }
+                        Err(e) => match e.downcast::<RuleError>() {
+                            Ok(RuleError::Invalid) => None,
+                            Err(e) => Some(Err(e)),
+                        },
+                    }
+                } else {
+                    duplicates += 1;
+                    None
+                }
+            })
+            .collect();
+        Ok(RuleOutput::continue_with(nodes?, duplicates))
+    }
+}
+
+impl<V, StashValue, F> SequenceRule<V, StashValue, F>
+where
+    V: NodePayload,
+    StashValue: NodePayload<Payload = V::Payload> + StashIndexable + From<V>,
+    F: for<'a> Fn(&[RuleProductionArg<'a, AnyMatch<StashValue>>]) -> RuleResult<V> + Send + Sync,
+{
+    pub fn new(
+        sym: Sym,
+        patterns: Vec<BoxedPattern<StashValue>>,
+        prod: F,
+    ) -> SequenceRule<V, StashValue, F> {
+        SequenceRule {
+            sym,
+            patterns,
+            production: prod,
+            adjacency: AdjacencyPolicy::default(),
+            _phantom: SendSyncPhantomData::new(),
+        }
+    }
+
+    /// Replaces what may separate the matches of consecutive patterns.
+    pub fn with_adjacency(mut self, adjacency: AdjacencyPolicy) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+
+    fn matches(
+        &self,
+        stash: &Stash<StashValue>,
+        sentence: &str,
+    ) -> CoreResult<PredicateMatches<Vec<AnyMatch<StashValue>>>> {
+        let mut slots: Vec<PredicateMatches<AnyMatch<StashValue>>> = vec![];
+        for pattern in self.patterns.iter() {
+            let matches = match slots.last() {
+                None => first_matches(&**pattern, stash, sentence, |starts| {
+                    for following in &self.patterns[1..] {
+                        if !extend_starts(&**following, stash, sentence, starts)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                })?,
+                Some(previous) => pattern.predicate_starting_at(
+                    stash,
+                    sentence,
+                    &successor_starts(previous, sentence, stash.tokens(), &self.adjacency),
+                )?,
+            };
+            if matches.is_empty() {
+                return Ok(PredicateMatches::with_status(matches.status));
+            }
+            slots.push(matches);
+        }
+        let (first, rest) = match slots.split_first() {
+            Some(split) => split,
+            None => return Ok(PredicateMatches::with_status(ParsingStatus::Exit)),
+        };
+        let successors: Vec<_> = rest
+            .iter()
+            .map(|matches| Successors::new(matches, stash.tokens(), &self.adjacency))
+            .collect();
+        let mut result = PredicateMatches::with_status(ParsingStatus::Continue);
+        let mut sequence = Vec::with_capacity(slots.len());
+        for m0 in first.iter() {
+            sequence.push(m0);
+            extend_sequences(&successors, sentence, &mut sequence, &mut result);
+            sequence.pop();
+        }
+        Ok(result)
+    }
+}
+
 #[cfg(test)]
 #[allow(unused_mut)]
 mod tests {
     use crate::helpers::BoundariesChecker;
-    use crate::pattern::{FilterNodePattern, Text};
+    use crate::pattern::{FilterNodePattern, NodeMatch, Text};
     use crate::rule::*;
     use crate::stash::Stash;
     use crate::{
-        AttemptFrom, InnerStashIndexable, Node, NodePayload, ParsedNode, Range, StashIndexable,
+        AttemptFrom, CoreResult, EvaluationStrategy, ForestNode, InnerStashIndexable, NodeId,
+        NodePayload, NodeRef, ParsingConfig, PredicateKey, Range, RuleSetBuilder, StashIndexable,
         SymbolTable,
     };
     use regex::Regex;
@@ -1130,19 +1710,21 @@ mod tests {
         );
         assert_eq!(
             svec4![
-                ParsedNode::new(
+                ForestNode::new(
                     ten,
                     10usize,
                     Range(8, 11),
                     Some(10usize),
-                    svec![Node::new(ten, Range(8, 11), None, svec![])]
+                    svec![NodeRef::Text(ten, Range(8, 11))],
+                    2
                 ),
-                ParsedNode::new(
+                ForestNode::new(
                     ten,
                     10usize,
                     Range(12, 15),
                     Some(10usize),
-                    svec![Node::new(ten, Range(12, 15), None, svec![])]
+                    svec![NodeRef::Text(ten, Range(12, 15))],
+                    2
                 )
             ],
             rule.apply(&Stash::default(), "foobar: ten ten")
@@ -1165,41 +1747,383 @@ mod tests {
             |a, b| Ok(a.value() + b.value()),
         );
         let mut stash = Stash::default();
-        stash.push(ParsedNode::new(
+        stash.push(ForestNode::new(
             st.sym("ten"),
             10,
             Range(8, 11),
             None,
             svec![],
+            1,
         ));
-        stash.push(ParsedNode::new(
+        stash.push(ForestNode::new(
             st.sym("ten"),
             10,
             Range(12, 15),
             None,
             svec![],
+            1,
         ));
 
         assert_eq!(
-            vec![(stash.values()[0].clone(), stash.values()[1].clone())],
+            vec![(
+                NodeMatch::new(NodeId(0), Range(8, 11), 10),
+                NodeMatch::new(NodeId(1), Range(12, 15), 10)
+            )],
             rule_consec
                 .matches(&stash, "foobar: ten ten")
                 .unwrap()
                 .matches
         );
         assert_eq!(
-            svec4![ParsedNode::new(
+            svec4![ForestNode::new(
                 st.sym("2 consecutive ints"),
                 20,
                 Range(8, 15),
                 Some(20),
-                svec![
-                    stash.values()[0].root_node.clone(),
-                    stash.values()[1].root_node.clone()
-                ]
+                svec![NodeRef::Node(NodeId(0)), NodeRef::Node(NodeId(1))],
+                2
             )],
             rule_consec.apply(&stash, "foobar: ten ten").unwrap().nodes
         );
+        let output = rule_consec.apply(&stash, "foobar: ten ten").unwrap();
+        stash.extend(output.nodes);
+        assert!(stash.has_derivation(
+            st.sym("2 consecutive ints"),
+            &svec![NodeRef::Node(NodeId(0)), NodeRef::Node(NodeId(1))]
+        ));
+        let output = rule_consec.apply(&stash, "foobar: ten ten").unwrap();
+        assert!(output.nodes.is_empty());
+        assert_eq!(1, output.duplicates);
+    }
+
+    #[test]
+    fn test_duplicate_derivations() {
+        let mut st = SymbolTable::default();
+        let ten = st.sym("ten");
+        let rule = Rule1::new(ten, reg!(st, usize, "ten"), |_| Ok(10usize));
+        let other_rule = Rule1::new(st.sym("other"), reg!(st, usize, "ten"), |_| Ok(10usize));
+        let mut stash = Stash::default();
+        let children = svec![NodeRef::Text(ten, Range(8, 11))];
+        stash.push(ForestNode::new(
+            ten,
+            10,
+            Range(8, 11),
+            None,
+            children.clone(),
+            2,
+        ));
+        assert!(stash.has_derivation(ten, &children));
+        assert!(!stash.has_derivation(st.sym("other"), &children));
+        assert!(!stash.has_derivation(ten, &svec![NodeRef::Text(ten, Range(12, 15))]));
+
+        let output = rule.apply(&stash, "foobar: ten ten").unwrap();
+        assert_eq!(
+            vec![Range(12, 15)],
+            output
+                .nodes
+                .iter()
+                .map(|n| n.byte_range)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, output.duplicates);
+        // the same children under another rule are another derivation
+        let output = other_rule.apply(&stash, "foobar: ten ten").unwrap();
+        assert_eq!(2, output.nodes.len());
+        assert_eq!(0, output.duplicates);
+    }
+
+    #[test]
+    fn test_rule_matches_adjacent_successors() {
+        let mut st = SymbolTable::default();
+        let rule_consec = Rule2::new(
+            st.sym("2 consecutive ints"),
+            (
+                AnyNodePattern::<usize>::new(),
+                AnyNodePattern::<usize>::new(),
+            ),
+            |a, b| Ok(a.value() + b.value()),
+        );
+        let mut stash = Stash::default();
+        for range in &[Range(0, 2), Range(4, 6), Range(7, 9), Range(13, 15)] {
+            stash.push(ForestNode::new(st.sym("int"), 10, *range, None, svec![], 1));
+        }
+        assert_eq!(
+            vec![
+                (
+                    NodeMatch::new(NodeId(0), Range(0, 2), 10),
+                    NodeMatch::new(NodeId(1), Range(4, 6), 10)
+                ),
+                (
+                    NodeMatch::new(NodeId(1), Range(4, 6), 10),
+                    NodeMatch::new(NodeId(2), Range(7, 9), 10)
+                ),
+            ],
+            rule_consec
+                .matches(&stash, "10  10 10 ab 10")
+                .unwrap()
+                .matches
+        );
+    }
+
+    #[test]
+    fn test_rule_with_optional_pattern() {
+        let mut st = SymbolTable::default();
+        let rule = Rule3::new(
+            st.sym("tens and units"),
+            (
+                AnyNodePattern::<usize>::new(),
+                optional(reg!(st, usize, "of")),
+                AnyNodePattern::<usize>::new(),
+            ),
+            |a, of, b| {
+                let of = of.get().map(|of| of.group(0).len() * 100).unwrap_or(0);
+                Ok(of + a.value() * 10 + b.value())
+            },
+        );
+        let mut stash = Stash::default();
+        for (value, range) in &[
+            (3, Range(0, 1)),
+            (5, Range(5, 6)),
+            (4, Range(8, 9)),
+            (6, Range(11, 12)),
+        ] {
+            stash.push(ForestNode::new(
+                st.sym("int"),
+                *value,
+                *range,
+                None,
+                svec![],
+                1,
+            ));
+        }
+        let output = rule.apply(&stash, "3 of 5, 4  6").unwrap();
+        assert_eq!(
+            svec4![
+                ForestNode::new(
+                    st.sym("tens and units"),
+                    235,
+                    Range(0, 6),
+                    Some(235),
+                    svec![
+                        NodeRef::Node(NodeId(0)),
+                        NodeRef::Text(st.sym("of"), Range(2, 4)),
+                        NodeRef::Node(NodeId(1))
+                    ],
+                    2
+                ),
+                ForestNode::new(
+                    st.sym("tens and units"),
+                    46,
+                    Range(8, 12),
+                    Some(46),
+                    svec![NodeRef::Node(NodeId(2)), NodeRef::Node(NodeId(3))],
+                    2
+                )
+            ],
+            output.nodes
+        );
+        // "6" only follows the empty match tried right before it
+        assert_eq!(0, output.duplicates);
+
+        // a first pattern only matches nothing right before the next match
+        let of = optional(reg!(st, usize, "of"));
+        assert_eq!(1, of.predicate(&stash, "3 of 5, 4  6").unwrap().len());
+        let leading = Rule2::new(
+            st.sym("maybe of"),
+            (of, AnyNodePattern::<usize>::new()),
+            |of, b| Ok(of.get().map(|_| 100).unwrap_or(0) + b.value()),
+        );
+        let starts: Vec<_> = leading
+            .matches(&stash, "3 of 5, 4  6")
+            .unwrap()
+            .into_iter()
+            .map(|(of, b)| (of.byte_range, b.byte_range()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Range(2, 4), Range(5, 6)),
+                (Range(0, 0), Range(0, 1)),
+                (Range(5, 5), Range(5, 6)),
+                (Range(8, 8), Range(8, 9)),
+                (Range(11, 11), Range(11, 12)),
+            ],
+            starts
+        );
+
+        // the gap around a skipped pattern is only charged once
+        let close = Rule3::new(
+            st.sym("close tens and units"),
+            (
+                AnyNodePattern::<usize>::new(),
+                optional(reg!(st, usize, "of")),
+                AnyNodePattern::<usize>::new(),
+            ),
+            |a, _, b| Ok(a.value() * 10 + b.value()),
+        )
+        .with_adjacency(AdjacencyPolicy::whitespace().with_max_gap(1));
+        let ranges: Vec<_> = close
+            .apply(&stash, "3 of 5, 4  6")
+            .unwrap()
+            .nodes
+            .into_iter()
+            .map(|n| n.byte_range)
+            .collect();
+        assert_eq!(vec![Range(0, 6)], ranges);
+    }
+
+    #[test]
+    fn test_rule_with_repeated_pattern() {
+        let mut st = SymbolTable::default();
+        let sum = Rule1::new(
+            st.sym("sum"),
+            one_or_more(AnyNodePattern::<usize>::new()),
+            |ints| Ok(ints.all().iter().map(|i| i.value()).sum::<usize>()),
+        );
+        let mut stash = Stash::default();
+        for (value, range) in &[(1, Range(0, 1)), (2, Range(2, 3)), (3, Range(4, 5))] {
+            stash.push(ForestNode::new(
+                st.sym("int"),
+                *value,
+                *range,
+                None,
+                svec![],
+                1,
+            ));
+        }
+        let nodes: Vec<_> = sum
+            .apply(&stash, "1 2 3")
+            .unwrap()
+            .nodes
+            .into_iter()
+            .map(|n| (n.value, n.byte_range, n.children.len()))
+            .collect();
+        assert_eq!(
+            vec![
+                (1, Range(0, 1), 1),
+                (3, Range(0, 3), 2),
+                (6, Range(0, 5), 3),
+                (2, Range(2, 3), 1),
+                (5, Range(2, 5), 2),
+                (3, Range(4, 5), 1),
+            ],
+            nodes
+        );
+        let from_two: Vec<_> = one_or_more(AnyNodePattern::<usize>::new())
+            .predicate_starting_at(&stash, "1 2 3", &[2].iter().cloned().collect())
+            .unwrap()
+            .into_iter()
+            .map(|m| m.byte_range)
+            .collect();
+        assert_eq!(vec![Range(2, 3), Range(2, 5)], from_two);
+        let capped: Vec<_> = one_or_more(AnyNodePattern::<usize>::new())
+            .with_max_chains(2)
+            .predicate(&stash, "1 2 3")
+            .unwrap()
+            .into_iter()
+            .map(|m| m.byte_range)
+            .collect();
+        assert_eq!(
+            vec![
+                Range(0, 1),
+                Range(0, 3),
+                Range(2, 3),
+                Range(2, 5),
+                Range(4, 5)
+            ],
+            capped
+        );
+
+        let prefixed = Rule2::new(
+            st.sym("prefixed"),
+            (
+                zero_or_more(reg!(st, usize, "plus")),
+                AnyNodePattern::<usize>::new(),
+            ),
+            |plus, int| Ok(plus.all().len() * 10 + int.value()),
+        );
+        let values: Vec<_> = prefixed
+            .apply(&stash, "1 2 3")
+            .unwrap()
+            .nodes
+            .into_iter()
+            .map(|n| n.value)
+            .collect();
+        assert_eq!(vec![1, 2, 3], values);
+        let mut stash = Stash::default();
+        stash.push(ForestNode::new(
+            st.sym("int"),
+            1,
+            Range(10, 11),
+            None,
+            svec![],
+            1,
+        ));
+        let nodes: Vec<_> = prefixed
+            .apply(&stash, "plus plus 1")
+            .unwrap()
+            .nodes
+            .into_iter()
+            .map(|n| (n.value, n.byte_range))
+            .collect();
+        assert_eq!(
+            vec![(21, Range(0, 11)), (11, Range(5, 11)), (1, Range(10, 11))],
+            nodes
+        );
+    }
+
+    /// Node pattern relying on the default `stash_indexes`.
+    struct LargeInts(PredicateKey);
+
+    impl Pattern<usize> for LargeInts {
+        type M = NodeMatch<usize>;
+        fn predicate(
+            &self,
+            stash: &Stash<usize>,
+            _sentence: &str,
+        ) -> CoreResult<PredicateMatches<NodeMatch<usize>>> {
+            Ok(PredicateMatches::continue_with(
+                stash.filter(self.0, |v: &usize| *v > 1000),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_agenda_reschedules_rules_of_any_index() {
+        use std::str::FromStr;
+        let rules = |strategy| {
+            let b = RuleSetBuilder::new(
+                BoundariesChecker::detailed(),
+                BoundariesChecker::separated_alphanumeric_word(),
+            );
+            b.rule_1_terminal("int", b.reg("\\d+").unwrap(), |t| {
+                Ok(usize::from_str(t.group(0))?)
+            });
+            b.rule_1_terminal("thousand", b.reg("thousand").unwrap(), |_| Ok(1000usize));
+            b.rule_2(
+                "times",
+                AnyNodePattern::<usize>::new(),
+                AnyNodePattern::<usize>::new(),
+                |a, b| Ok(a.value() * b.value()),
+            );
+            b.rule_1("large", LargeInts(PredicateKey::fresh()), |a| {
+                Ok(a.value() + 1)
+            });
+            let mut rule_set = b.build();
+            rule_set.set_config(ParsingConfig {
+                strategy,
+                ..ParsingConfig::default()
+            });
+            rule_set
+        };
+        let expected = rules(EvaluationStrategy::FixedPoint)
+            .apply_all("2 thousand")
+            .unwrap();
+        let nodes = rules(EvaluationStrategy::Agenda)
+            .apply_all("2 thousand")
+            .unwrap();
+        // 2000 is only produced by the second pass, "large" must run again
+        assert!(nodes.iter().any(|n| n.value == 2001));
+        assert_eq!(expected, nodes);
     }
 
     #[test]
@@ -1210,12 +2134,13 @@ mod tests {
             Ok(usize::from_str(&*a.group(0))?)
         });
         assert_eq!(
-            svec4![ParsedNode::new(
+            svec4![ForestNode::new(
                 st.sym("int"),
                 42,
                 Range(8, 10),
                 Some(42),
-                svec![Node::new(st.sym("\\d+"), Range(8, 10), None, svec![])]
+                svec![NodeRef::Text(st.sym("\\d+"), Range(8, 10))],
+                2
             )],
             rule_int
                 .apply(&Stash::default(), "foobar: 42")
@@ -1223,5 +2148,4 @@ mod tests {
                 .nodes
         );
     }
-
 }
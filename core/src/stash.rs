@@ -1,9 +1,16 @@
-use crate::pattern::Match;
-use crate::{AttemptFrom, NodePayload, ParsedNode};
-use std::collections::HashMap;
+use crate::helpers::WordBounds;
+use crate::pattern::{NodeMatch, PredicateMatches, Text};
+use crate::tokenizer::Tokens;
+use crate::{AttemptFrom, CoreResult, Node, NodePayload, ParsedNode, ParsingStatus, Range, Sym};
+use regex::SetMatches;
+use smallvec::SmallVec;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::mem;
 use std::slice::Iter;
-use std::vec::IntoIter;
+use std::sync;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub trait StashIndexable {
     type Index: Hash + Eq;
@@ -15,9 +22,94 @@ pub trait InnerStashIndexable {
     fn index() -> Self::Index;
 }
 
+/// Position of a node in the per-sentence forest held by the `Stash`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+/// Child of a forest node: either a node of the stash, or a text match which
+/// is kept inline as it has no children.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NodeRef {
+    Node(NodeId),
+    Text(Sym, Range),
+    /// Fuzzy text match, with its edit distance
+    FuzzyText(Sym, Range, usize),
+}
+
+pub type ChildrenRefs = SmallVec<[NodeRef; 2]>;
+
+/// Node of the per-sentence forest. Children are referenced by id, the
+/// `Node` trees exposed by `ParsedNode` are only built once parsing is done.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ForestNode<V: NodePayload> {
+    pub rule_sym: Sym,
+    pub byte_range: Range,
+    pub payload: Option<V::Payload>,
+    pub children: ChildrenRefs,
+    pub height: usize,
+    pub value: V,
+}
+
+impl<V: NodePayload> ForestNode<V> {
+    pub fn new(
+        rule_sym: Sym,
+        value: V,
+        byte_range: Range,
+        payload: Option<V::Payload>,
+        children: ChildrenRefs,
+        height: usize,
+    ) -> ForestNode<V> {
+        ForestNode {
+            rule_sym,
+            byte_range,
+            payload,
+            children,
+            height,
+            value,
+        }
+    }
+}
+
+/// Identifies a pattern predicate whose results are memoized by the stash.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PredicateKey(usize);
+
+impl PredicateKey {
+    pub fn fresh() -> PredicateKey {
+        static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+        PredicateKey(NEXT_KEY.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Key of the text matches cached by the stash for a sentence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextCacheKey {
+    /// Regex pattern with this id in the text scan of the rule set, which
+    /// may rule it out without running it.
+    Scan(usize),
+    /// Gazetteer or fuzzy pattern, which the text scan knows nothing about.
+    Lexicon(usize),
+}
+
+#[derive(Default)]
+struct PredicateMemo {
+    /// Number of nodes of the index already tested
+    tested: usize,
+    positions: Vec<usize>,
+}
+
 pub struct Stash<S: StashIndexable + NodePayload> {
-    values: Vec<ParsedNode<S>>,
+    values: Vec<ForestNode<S>>,
     index: HashMap<S::Index, Vec<usize>>,
+    by_start: HashMap<usize, Vec<usize>>,
+    derivations: HashMap<Sym, HashSet<ChildrenRefs>>,
+    text_scan: Option<SetMatches>,
+    tokens: Option<Tokens>,
+    word_bounds: WordBounds,
+    text_cache: RefCell<HashMap<TextCacheKey, Vec<Text<S>>>>,
+    predicate_memos: RefCell<HashMap<PredicateKey, PredicateMemo>>,
+    predicate_memo_hits: Cell<usize>,
+    predicate_memo_misses: Cell<usize>,
 }
 
 impl<S: StashIndexable + NodePayload> Default for Stash<S> {
@@ -25,74 +117,304 @@ impl<S: StashIndexable + NodePayload> Default for Stash<S> {
         Stash {
             values: vec![],
             index: HashMap::new(),
+            by_start: HashMap::new(),
+            derivations: HashMap::new(),
+            text_scan: None,
+            tokens: None,
+            word_bounds: WordBounds::default(),
+            text_cache: RefCell::new(HashMap::new()),
+            predicate_memos: RefCell::new(HashMap::new()),
+            predicate_memo_hits: Cell::new(0),
+            predicate_memo_misses: Cell::new(0),
         }
     }
 }
 
 impl<S: StashIndexable + NodePayload> Stash<S> {
-    pub fn extend(&mut self, nodes: Vec<ParsedNode<S>>) {
+    pub fn extend<I: IntoIterator<Item = ForestNode<S>>>(&mut self, nodes: I) {
         for node in nodes.into_iter() {
             self.push(node);
         }
     }
-    pub fn push(&mut self, node: ParsedNode<S>) {
+
+    pub fn push(&mut self, node: ForestNode<S>) -> NodeId {
         let node_position = self.values.len();
         let node_index = node.value.index();
+        let start = node.byte_range.0;
+        self.derivations
+            .entry(node.rule_sym)
+            .or_default()
+            .insert(node.children.clone());
         self.values.push(node);
         self.index
             .entry(node_index)
             .or_insert(vec![])
             .push(node_position);
+        self.by_start
+            .entry(start)
+            .or_insert(vec![])
+            .push(node_position);
+        NodeId(node_position)
     }
 
-    pub fn filter<V, F>(&self, predicate: F) -> Vec<ParsedNode<V>>
+    /// Nodes of the stash seen as a `V` satisfying `predicate`. The result
+    /// is memoized under `key`, so only the nodes added since the previous
+    /// call with the same key are tested.
+    pub fn filter<V, F>(&self, key: PredicateKey, predicate: F) -> Vec<NodeMatch<V>>
     where
         V: InnerStashIndexable<Index = S::Index>
             + NodePayload<Payload = S::Payload>
             + AttemptFrom<S>,
         F: Fn(&V) -> bool,
     {
-        self.index
-            .get(&V::index())
-            .map(|nodes| {
-                nodes
-                    .iter()
-                    .filter_map(|position| {
-                        let ref node = self.values[*position];
-                        if let Some(v) = V::attempt_from(node.value.clone()) {
-                            if (predicate)(&v) {
-                                Some(ParsedNode::new(
-                                    node.root_node.rule_sym,
-                                    v,
-                                    node.byte_range(),
-                                    node.root_node.payload.clone(),
-                                    node.root_node.children.clone(),
-                                ))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect()
+        self.node_matches(self.memoized_positions(key, &predicate))
+    }
+
+    /// Same as `filter`, only considering the nodes starting at one of the
+    /// `starts` byte offsets.
+    pub fn filter_starting_at<V, F>(
+        &self,
+        key: PredicateKey,
+        starts: &HashSet<usize>,
+        predicate: F,
+    ) -> Vec<NodeMatch<V>>
+    where
+        V: InnerStashIndexable<Index = S::Index>
+            + NodePayload<Payload = S::Payload>
+            + AttemptFrom<S>,
+        F: Fn(&V) -> bool,
+    {
+        let matching = self.memoized_positions(key, &predicate);
+        let mut positions: Vec<usize> = starts
+            .iter()
+            .filter_map(|start| self.by_start.get(start))
+            .flat_map(|nodes| nodes.iter().cloned())
+            .filter(|position| matching.binary_search(position).is_ok())
+            .collect();
+        positions.sort();
+        self.node_matches(positions)
+    }
+
+    /// Positions of the nodes of index `V::index()` satisfying `predicate`,
+    /// in increasing order.
+    fn memoized_positions<V, F>(&self, key: PredicateKey, predicate: &F) -> Vec<usize>
+    where
+        V: InnerStashIndexable<Index = S::Index> + AttemptFrom<S>,
+        F: Fn(&V) -> bool,
+    {
+        let nodes = match self.index.get(&V::index()) {
+            Some(nodes) => nodes,
+            None => return vec![],
+        };
+        let mut predicate_memos = self.predicate_memos.borrow_mut();
+        let memo = predicate_memos.entry(key).or_default();
+        if memo.tested == nodes.len() {
+            self.predicate_memo_hits
+                .set(self.predicate_memo_hits.get() + 1);
+        } else {
+            self.predicate_memo_misses
+                .set(self.predicate_memo_misses.get() + 1);
+            for position in &nodes[memo.tested..] {
+                let satisfied = V::attempt_from(self.values[*position].value.clone())
+                    .map(|v| (predicate)(&v))
+                    .unwrap_or(false);
+                if satisfied {
+                    memo.positions.push(*position);
+                }
+            }
+            memo.tested = nodes.len();
+        }
+        memo.positions.clone()
+    }
+
+    fn node_matches<V>(&self, positions: Vec<usize>) -> Vec<NodeMatch<V>>
+    where
+        V: NodePayload<Payload = S::Payload> + AttemptFrom<S>,
+    {
+        positions
+            .into_iter()
+            .filter_map(|position| {
+                let node = &self.values[position];
+                V::attempt_from(node.value.clone())
+                    .map(|v| NodeMatch::new(NodeId(position), node.byte_range, v))
             })
-            .unwrap_or(vec![])
+            .collect()
     }
 
-    pub fn iter(&self) -> Iter<ParsedNode<S>> {
-        self.values.iter()
+    /// Number of memoized predicate results reused and (re)computed.
+    pub fn predicate_memo_stats(&self) -> (usize, usize) {
+        (
+            self.predicate_memo_hits.get(),
+            self.predicate_memo_misses.get(),
+        )
+    }
+
+    /// Whether a node produced by rule `rule_sym` from `children` is already
+    /// in the stash.
+    pub fn has_derivation(&self, rule_sym: Sym, children: &ChildrenRefs) -> bool {
+        self.derivations
+            .get(&rule_sym)
+            .map(|derivations| derivations.contains(children))
+            .unwrap_or(false)
+    }
+
+    /// Records which text patterns may match the sentence, by scan id.
+    pub fn set_text_scan(&mut self, text_scan: SetMatches) {
+        self.text_scan = Some(text_scan)
+    }
+
+    /// Restricts the text matches to the ones aligned on `tokens`.
+    pub fn set_tokens(&mut self, tokens: Tokens) {
+        self.tokens = Some(tokens)
+    }
+
+    /// Tokens of the sentence, if the rule set has a tokenizer.
+    pub fn tokens(&self) -> Option<&Tokens> {
+        self.tokens.as_ref()
+    }
+
+    /// Unicode word boundaries of the sentence, shared by all the boundaries
+    /// checks.
+    pub fn word_bounds(&self) -> &WordBounds {
+        &self.word_bounds
+    }
+
+    /// Takes the word boundaries out of the stash, to check boundaries after
+    /// the stash is consumed.
+    pub fn take_word_bounds(&mut self) -> WordBounds {
+        mem::take(&mut self.word_bounds)
+    }
+
+    /// Whether `range` starts and ends on token boundaries, always true
+    /// without tokens.
+    pub fn is_token_aligned(&self, range: Range) -> bool {
+        self.tokens
+            .as_ref()
+            .map(|t| t.is_aligned(range))
+            .unwrap_or(true)
+    }
+
+    /// Keeps the text matches aligned on the tokens.
+    pub fn token_aligned(
+        &self,
+        mut matches: PredicateMatches<Text<S>>,
+    ) -> PredicateMatches<Text<S>> {
+        if self.tokens.is_some() {
+            matches
+                .matches
+                .retain(|t| self.is_token_aligned(t.byte_range));
+            matches.exit_if_empty()
+        } else {
+            matches
+        }
+    }
+
+    /// Matches of the text pattern with cache key `key`, only computed by
+    /// `scan` once per sentence, and not at all if the text scan rules it
+    /// out. Only the matches aligned on the tokens are kept.
+    pub fn text_matches<F>(
+        &self,
+        key: Option<TextCacheKey>,
+        scan: F,
+    ) -> CoreResult<PredicateMatches<Text<S>>>
+    where
+        F: FnOnce() -> CoreResult<PredicateMatches<Text<S>>>,
+    {
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(self.token_aligned(scan()?)),
+        };
+        if let (TextCacheKey::Scan(scan_id), Some(ref text_scan)) = (key, &self.text_scan) {
+            if scan_id < text_scan.len() && !text_scan.matched(scan_id) {
+                return Ok(PredicateMatches::with_status(ParsingStatus::Exit));
+            }
+        }
+        if let Some(texts) = self.text_cache.borrow().get(&key) {
+            return Ok(PredicateMatches::continue_with(texts.clone()).exit_if_empty());
+        }
+        let matches = self.token_aligned(scan()?);
+        self.text_cache
+            .borrow_mut()
+            .insert(key, matches.matches.clone());
+        Ok(matches)
+    }
+
+    pub fn get(&self, id: NodeId) -> &ForestNode<S> {
+        &self.values[id.0]
     }
-    pub fn into_iter(self) -> IntoIter<ParsedNode<S>> {
-        self.values.into_iter()
+
+    /// Height of the tree of a node made of `children`.
+    pub fn height(&self, children: &[NodeRef]) -> usize {
+        1 + children
+            .iter()
+            .map(|c| match c {
+                NodeRef::Node(id) => self.get(*id).height,
+                NodeRef::Text(..) | NodeRef::FuzzyText(..) => 1,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn iter(&self) -> Iter<ForestNode<S>> {
+        self.values.iter()
     }
 
     pub fn len(&self) -> usize {
         self.values.len()
     }
 
+    /// Builds the `ParsedNode` of every forest node matching `predicate`.
+    /// Subtrees shared in the forest are shared in the resulting trees.
+    pub fn into_parsed_nodes<F>(self, predicate: F) -> Vec<ParsedNode<S>>
+    where
+        F: Fn(&ForestNode<S>) -> bool,
+    {
+        let mut trees: Vec<Option<sync::Arc<Node<S::Payload>>>> = vec![None; self.values.len()];
+        let mut parsed_nodes = vec![];
+        for position in 0..self.values.len() {
+            if predicate(&self.values[position]) {
+                let root_node = self.tree(NodeId(position), &mut trees);
+                parsed_nodes.push(ParsedNode {
+                    root_node,
+                    value: self.values[position].value.clone(),
+                });
+            }
+        }
+        parsed_nodes
+    }
+
+    fn tree(
+        &self,
+        id: NodeId,
+        trees: &mut Vec<Option<sync::Arc<Node<S::Payload>>>>,
+    ) -> sync::Arc<Node<S::Payload>> {
+        if let Some(ref tree) = trees[id.0] {
+            return tree.clone();
+        }
+        let node = self.get(id);
+        let children = node
+            .children
+            .iter()
+            .map(|c| match c {
+                NodeRef::Node(child_id) => self.tree(*child_id, trees),
+                NodeRef::Text(sym, byte_range) => Node::text(*sym, *byte_range, None),
+                NodeRef::FuzzyText(sym, byte_range, edit_distance) => {
+                    Node::text(*sym, *byte_range, Some(*edit_distance))
+                }
+            })
+            .collect();
+        let tree = Node::new(
+            node.rule_sym,
+            node.byte_range,
+            node.payload.clone(),
+            children,
+        );
+        trees[id.0] = Some(tree.clone());
+        tree
+    }
+
     #[cfg(test)]
-    pub fn values(&self) -> &Vec<ParsedNode<S>> {
+    pub fn values(&self) -> &Vec<ForestNode<S>> {
         self.values.as_ref()
     }
 }
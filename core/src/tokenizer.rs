@@ -0,0 +1,112 @@
+use crate::range::Range;
+use std::collections::HashMap;
+
+/// Splits a sentence in tokens. When a `RuleSet` has a tokenizer, text
+/// matches must start and end on token boundaries.
+pub trait Tokenizer {
+    fn tokenize(&self, sentence: &str) -> Vec<Range>;
+}
+
+/// Tokenizer producing the maximal runs of alphanumeric chars, and a token
+/// per other char that is not whitespace.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WordTokenizer;
+
+impl Tokenizer for WordTokenizer {
+    fn tokenize(&self, sentence: &str) -> Vec<Range> {
+        let mut tokens = vec![];
+        let mut word_start = None;
+        for (ix, c) in sentence.char_indices() {
+            if c.is_alphanumeric() {
+                word_start.get_or_insert(ix);
+                continue;
+            }
+            if let Some(start) = word_start.take() {
+                tokens.push(Range(start, ix));
+            }
+            if !c.is_whitespace() {
+                tokens.push(Range(ix, ix + c.len_utf8()));
+            }
+        }
+        if let Some(start) = word_start {
+            tokens.push(Range(start, sentence.len()));
+        }
+        tokens
+    }
+}
+
+/// Tokens of a sentence, indexed by their boundaries.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Tokens {
+    spans: Vec<Range>,
+    by_start: HashMap<usize, usize>,
+    by_end: HashMap<usize, usize>,
+}
+
+impl Tokens {
+    pub fn new(spans: Vec<Range>) -> Tokens {
+        let by_start = spans.iter().enumerate().map(|(ix, s)| (s.0, ix)).collect();
+        let by_end = spans.iter().enumerate().map(|(ix, s)| (s.1, ix)).collect();
+        Tokens {
+            spans,
+            by_start,
+            by_end,
+        }
+    }
+
+    pub fn spans(&self) -> &[Range] {
+        &self.spans
+    }
+
+    /// Range of the indices of the tokens exactly covering `byte_range`, if
+    /// it starts and ends on token boundaries.
+    pub fn token_range(&self, byte_range: Range) -> Option<Range> {
+        let first = *self.by_start.get(&byte_range.0)?;
+        if byte_range.0 == byte_range.1 {
+            return Some(Range(first, first));
+        }
+        let last = *self.by_end.get(&byte_range.1)?;
+        if last < first {
+            return None;
+        }
+        Some(Range(first, last + 1))
+    }
+
+    /// Whether a token starts at byte offset `position`.
+    pub fn is_start(&self, position: usize) -> bool {
+        self.by_start.contains_key(&position)
+    }
+
+    pub fn is_aligned(&self, byte_range: Range) -> bool {
+        self.token_range(byte_range).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_tokenizer() {
+        let sentence = "at 10:30, l'été";
+        let tokens = Tokens::new(WordTokenizer.tokenize(sentence));
+        assert_eq!(
+            &[
+                Range(0, 2),
+                Range(3, 5),
+                Range(5, 6),
+                Range(6, 8),
+                Range(8, 9),
+                Range(10, 11),
+                Range(11, 12),
+                Range(12, 17),
+            ][..],
+            tokens.spans()
+        );
+        assert_eq!(Some(Range(1, 4)), tokens.token_range(Range(3, 8)));
+        assert_eq!(Some(Range(3, 3)), tokens.token_range(Range(6, 6)));
+        assert_eq!(None, tokens.token_range(Range(4, 8)));
+        assert_eq!(None, tokens.token_range(Range(3, 7)));
+        assert_eq!(None, tokens.token_range(Range(9, 11)));
+    }
+}
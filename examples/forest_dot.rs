@@ -0,0 +1,97 @@
+//! Prints the parse forest of a sentence as a Graphviz DOT graph, using a
+//! toy grammar of numbers.
+//!
+//!     cargo run --example forest_dot -- "12 thousands and 3 hundreds" | dot -Tsvg > forest.svg
+
+extern crate rustling;
+extern crate fnv;
+extern crate serde;
+
+use rustling::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Integer(u64);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Multiplier(u64);
+
+rustling_value! {
+    #[doc="values of the toy grammar"]
+    #[derive(Clone,PartialEq,Debug)]
+    ToyValue ToyValueKind {
+        Integer(Integer),
+        Multiplier(Multiplier),
+    }
+
+    fn latent(v: &ToyValue) -> bool {
+        matches!(v, ToyValue::Multiplier(_))
+    }
+
+    fn extract_payload(v: &ToyValue) -> Option<u64> {
+        None
+    }
+}
+
+#[derive(Debug, Hash, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct RuleFeat(Sym);
+
+impl Feature for RuleFeat {}
+
+struct RuleFeatExtractor;
+
+impl FeatureExtractor<ToyValue, RuleFeat> for RuleFeatExtractor {
+    fn for_parsed_node(&self, node: &ParsedNode<ToyValue>) -> Input<RuleId, RuleFeat> {
+        self.for_node(&node.root_node)
+    }
+
+    fn for_node(&self, node: &Node<u64>) -> Input<RuleId, RuleFeat> {
+        Input {
+            classifier_id: RuleId(node.rule_sym),
+            features: node.children.iter().map(|c| RuleFeat(c.rule_sym)).collect(),
+            children: node.children.iter().map(|c| self.for_node(c)).collect(),
+        }
+    }
+}
+
+fn rules() -> RustlingResult<RuleSet<ToyValue>> {
+    let b = RuleSetBuilder::new(
+        BoundariesChecker::detailed(),
+        BoundariesChecker::separated_alphanumeric_word(),
+    );
+    b.rule_1("integer (numeric)", b.reg(r#"\d{1,18}"#)?, |text_match| {
+        Ok(Integer(u64::from_str(text_match.group(0))?))
+    });
+    b.rule_1("hundreds", b.reg("hundreds?")?, |_| Ok(Multiplier(100)));
+    b.rule_1("thousands", b.reg("thousands?")?, |_| Ok(Multiplier(1000)));
+    b.rule_2(
+        "integer multiplied",
+        dim!(Integer),
+        dim!(Multiplier),
+        |a, b| Ok(Integer(a.value().0 * b.value().0)),
+    );
+    b.rule_3(
+        "integer sum",
+        dim!(Integer),
+        b.reg("and")?,
+        dim!(Integer),
+        |a, _, b| Ok(Integer(a.value().0 + b.value().0)),
+    );
+    Ok(b.build())
+}
+
+fn main() -> RustlingResult<()> {
+    let sentence = ::std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "12 thousands and 3 hundreds".to_string());
+    let parser = Parser::new(
+        rules()?,
+        Model {
+            classifiers: fnv::FnvHashMap::default(),
+        },
+        RuleFeatExtractor,
+    );
+    print!("{}", parser.forest_dot(&sentence)?);
+    Ok(())
+}
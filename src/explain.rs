@@ -0,0 +1,126 @@
+use crate::{Node, NodeId, Range, Sym};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Derivation of a parsed value, node by node, for grammar debugging.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    /// Name of the rule or text pattern of the node
+    pub rule: String,
+    /// Byte offsets of the matched text, in the original input
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// Value of the node, formatted with `Debug`, `None` for text leaves
+    pub value: Option<String>,
+    /// Classifier score of the subtree rooted at the node
+    pub probalog: f32,
+    pub children: Vec<Explanation>,
+}
+
+impl Explanation {
+    /// One line per node, children indented under their parent.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text, 0);
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        ::serde_json::to_string_pretty(self).expect("explanations are serializable")
+    }
+
+    fn write_text(&self, text: &mut String, depth: usize) {
+        let _ = write!(
+            text,
+            "{:indent$}{} {:?} [{}..{}]",
+            "",
+            self.rule,
+            self.text,
+            self.start,
+            self.end,
+            indent = 2 * depth
+        );
+        if let Some(ref value) = self.value {
+            let _ = write!(text, " = {}", value);
+        }
+        let _ = writeln!(text, " (probalog: {:.3})", self.probalog);
+        for child in &self.children {
+            child.write_text(text, depth + 1);
+        }
+    }
+}
+
+/// Identity of a node in a `ForestDot`: the nodes built by rules have an id
+/// in the forest, but text leaves are built again for each parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DotKey {
+    Shared(NodeId),
+    Leaf(Sym, Range),
+}
+
+/// Graphviz DOT graph of parse trees, where a node shared by several trees
+/// is only written once.
+#[derive(Debug, Default)]
+pub(crate) struct ForestDot {
+    ids: HashMap<DotKey, usize>,
+    labels: Vec<Vec<String>>,
+    roots: Vec<bool>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl ForestDot {
+    /// Id in the graph of `node`, whose id in the forest is `forest_id`,
+    /// `None` for text leaves, and whether it was already there.
+    pub(crate) fn node<P: Clone>(
+        &mut self,
+        node: &Node<P>,
+        forest_id: Option<NodeId>,
+    ) -> (usize, bool) {
+        let key = match forest_id {
+            Some(forest_id) => DotKey::Shared(forest_id),
+            None => DotKey::Leaf(node.rule_sym, node.byte_range),
+        };
+        let next = self.labels.len();
+        let id = *self.ids.entry(key).or_insert(next);
+        if id == next {
+            self.labels.push(vec![]);
+            self.roots.push(false);
+        }
+        (id, id < next)
+    }
+
+    pub(crate) fn label(&mut self, id: usize, line: String) {
+        self.labels[id].push(line)
+    }
+
+    /// Marks `id` as the root of a parsed value.
+    pub(crate) fn root(&mut self, id: usize) {
+        self.roots[id] = true
+    }
+
+    pub(crate) fn edge(&mut self, from: usize, to: usize) {
+        self.edges.push((from, to))
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut dot = String::from("digraph forest {\n  node [shape=box];\n");
+        for (id, lines) in self.labels.iter().enumerate() {
+            let label: Vec<String> = lines
+                .iter()
+                .map(|l| l.replace('\\', "\\\\").replace('"', "\\\""))
+                .collect();
+            let _ = write!(dot, "  n{} [label=\"{}\"", id, label.join("\\n"));
+            if self.roots[id] {
+                dot.push_str(", peripheries=2");
+            }
+            dot.push_str("];\n");
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(dot, "  n{} -> n{};", from, to);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
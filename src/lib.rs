@@ -3,27 +3,49 @@ extern crate failure;
 extern crate fnv;
 extern crate rustling_core;
 extern crate rustling_ml;
+extern crate serde_json;
+extern crate unicode_normalization;
 
+pub use explain::Explanation;
+use explain::ForestDot;
+pub use normalizer::{
+    AccentStripping, CaseFolding, Nfkc, Normalized, Normalizer, NormalizerChain,
+    WhitespaceCollapsing,
+};
 pub use rustling_core::regex;
 pub use rustling_core::{
-    AttemptFrom, AttemptInto, BoundariesChecker, InnerStashIndexable, Node, NodePayload,
-    ParsedNode, Range, RuleSet, RuleSetBuilder, StashIndexable, Sym,
+    alphabetic_class, alphanumeric_class, detailed_class, is_unsegmented_script, AdjacencyPolicy,
+    AttemptFrom, AttemptInto, BoundariesChecker, EvaluationStrategy, Forest, InnerStashIndexable,
+    Node, NodeId, NodePayload, OffsetTable, ParsedNode, ParsingConfig, ParsingLimit, ParsingReport,
+    Range, RuleSet, RuleSetBuilder, StashIndexable, Sym, Tokenizer, Tokens, ValidBoundariesOption,
+    WordTokenizer,
 };
 pub use rustling_core::{RuleError, RuleResult};
 pub use rustling_ml::{ClassId, Classifier, ClassifierId, Feature, Input, Model};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::sync::Arc;
+pub use tagger::{
+    KeepAllTagger, LatentPolicy, LongestSpanTagger, Segmentation, SegmentationObjective,
+    SegmentationScore, SegmentationTagger, WeightedSpanTagger,
+};
 pub use train::{Check, Example};
 
 #[macro_use]
 pub mod macros;
+pub mod explain;
+pub mod normalizer;
+pub mod tagger;
 pub mod train;
 
 pub mod core {
     pub use rustling_core::pattern::{
-        AnyNodePattern, FilterNodePattern, TextNegLHPattern, TextPattern,
+        one_or_more, optional, zero_or_more, AnyMatch, AnyNodePattern, AnyOf, AnyOfMatch,
+        BoxedPattern, FilterNodePattern, FuzzyPattern, GazetteerPattern, IntoAnyPattern,
+        Lookaround, LookaroundKind, NodeMatch, OptionalMatch, OptionalPattern, RepeatedMatch,
+        RepeatedPattern, TextLookaroundPattern, TextNegLHPattern, TextPattern,
     };
-    pub use rustling_core::rule::{Rule1, Rule2, Rule3, Rule4, Rule5, Rule6};
+    pub use rustling_core::rule::{Rule1, Rule2, Rule3, Rule4, Rule5, Rule6, SequenceRule};
 }
 
 pub type RustlingResult<T> = Result<T, ::failure::Error>;
@@ -45,10 +67,15 @@ pub trait Value: NodePayload {
 /// Match holder for the Parser.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParserMatch<V> {
-    /// Range in bytes of matched area
+    /// Range in bytes of matched area, in the original input
     pub byte_range: Range,
-    /// Range in char of matched area
+    /// Range in char of matched area, in the original input
     pub char_range: Range,
+    /// Range in UTF-16 code units of matched area, in the original input
+    pub utf16_range: Range,
+    /// Range of the indices of the matched tokens, in the tokens of the
+    /// original input, when the parser has a tokenizer
+    pub token_range: Option<Range>,
     /// Parsing tree height
     pub parsing_tree_height: usize,
     /// Number of nodes in the parsing tree
@@ -94,6 +121,17 @@ pub struct Candidate<V: Value, ResolvedV> {
     pub tagged: bool,
 }
 
+/// Distinct values parsed on a span selected by a tagger, the most probable
+/// first.
+#[derive(Debug, Clone)]
+pub struct SpanAlternatives<V: Value, ResolvedV> {
+    pub byte_range: Range,
+    pub alternatives: Vec<Candidate<V, ResolvedV>>,
+}
+
+type RawCandidates<V> = Vec<(ParsedNode<V>, ParserMatch<V>)>;
+type Candidates<V, ResolvedV> = Vec<Candidate<V, ResolvedV>>;
+
 pub struct Parser<V, Feat, Extractor>
 where
     V: Value + StashIndexable,
@@ -103,6 +141,7 @@ where
     rules: RuleSet<V>,
     model: Model<RuleId, Truth, Feat>,
     extractor: Extractor,
+    normalizer: Option<Box<dyn Normalizer + Send + Sync>>,
 }
 
 impl<V, Feat, Extractor> Parser<V, Feat, Extractor>
@@ -121,19 +160,53 @@ where
             rules,
             model,
             extractor,
+            normalizer: None,
         }
     }
 
-    fn raw_candidates(&self, input: &str) -> RustlingResult<Vec<(ParsedNode<V>, ParserMatch<V>)>> {
-        self.rules
-            .apply_all(input)?
+    /// Normalizes the inputs with `normalizer` before parsing them. The
+    /// ranges of the matches still refer to the original inputs.
+    pub fn set_normalizer<N: Normalizer + Send + Sync + 'static>(&mut self, normalizer: N) {
+        self.normalizer = Some(Box::new(normalizer))
+    }
+
+    pub fn parsing_config(&self) -> &ParsingConfig {
+        self.rules.config()
+    }
+
+    pub fn set_parsing_config(&mut self, config: ParsingConfig) {
+        self.rules.set_config(config)
+    }
+
+    /// Only matches whole tokens of `tokenizer`, and reports the token
+    /// range of the matches. The tokenizer is run on the normalized input.
+    pub fn set_tokenizer<T: Tokenizer + Send + Sync + 'static>(&mut self, tokenizer: T) {
+        self.rules.set_tokenizer(tokenizer)
+    }
+
+    fn normalize(&self, input: &str) -> Normalized {
+        match self.normalizer {
+            Some(ref normalizer) => normalizer.normalize(input),
+            None => Normalized::identity(input),
+        }
+    }
+
+    fn raw_candidates(&self, input: &str) -> RustlingResult<(RawCandidates<V>, ParsingReport)> {
+        let normalized = self.normalize(input);
+        let (nodes, report) = self.rules.apply_all_with_report(&normalized.text)?;
+        let tokens = self.rules.tokens(input);
+        let offsets = OffsetTable::new(input);
+        let candidates = nodes
             .into_iter()
             .map(|p| {
                 let features: Input<RuleId, Feat> = self.extractor.for_parsed_node(&p);
                 let probalog = self.model.classify(&features, &Truth(true))?;
+                let byte_range = normalized.original_range(p.root_node.byte_range);
                 let pm = ParserMatch {
-                    byte_range: p.root_node.byte_range,
-                    char_range: p.root_node.byte_range.char_range(input),
+                    byte_range,
+                    char_range: offsets.char_range(byte_range),
+                    utf16_range: offsets.utf16_range(byte_range),
+                    token_range: tokens.as_ref().and_then(|t| t.token_range(byte_range)),
                     value: p.value.clone().into(),
                     parsing_tree_height: p.root_node.height(),
                     parsing_tree_num_nodes: p.root_node.num_nodes(),
@@ -142,7 +215,131 @@ where
                 };
                 Ok((p, pm))
             })
-            .collect()
+            .collect::<RustlingResult<_>>()?;
+        Ok((candidates, report))
+    }
+
+    /// Derivation trees of all the values parsed from `input`, the most
+    /// probable first.
+    pub fn explain(&self, input: &str) -> RustlingResult<Vec<Explanation>> {
+        let normalized = self.normalize(input);
+        let forest = self.rules.forest(&normalized.text)?;
+        let mut explanations = forest
+            .roots
+            .iter()
+            .map(|&root| {
+                let node = &forest.nodes[root].root_node;
+                self.explain_node(&forest, Some(NodeId(root)), node, input, &normalized)
+            })
+            .collect::<RustlingResult<Vec<_>>>()?;
+        explanations.sort_by(|a, b| {
+            b.probalog
+                .partial_cmp(&a.probalog)
+                .unwrap_or(::std::cmp::Ordering::Equal)
+        });
+        Ok(explanations)
+    }
+
+    /// Graphviz DOT graph of all the nodes built from `input`, with the
+    /// rule names, matched ranges, classifier scores and values of the
+    /// nodes. Nodes shared by several trees appear once, and the roots of
+    /// parsed values, the ones with valid match boundaries, are doubly
+    /// circled.
+    pub fn forest_dot(&self, input: &str) -> RustlingResult<String> {
+        let normalized = self.normalize(input);
+        let mut dot = ForestDot::default();
+        let forest = self.rules.forest(&normalized.text)?;
+        for (position, p) in forest.nodes.iter().enumerate() {
+            let id = Some(NodeId(position));
+            self.dot_node(&mut dot, &forest, id, &p.root_node, input, &normalized)?;
+        }
+        for (position, p) in forest.nodes.iter().enumerate() {
+            let (id, _) = dot.node(&p.root_node, Some(NodeId(position)));
+            dot.label(id, format!("= {:?}", p.value));
+        }
+        for &root in &forest.roots {
+            let (id, _) = dot.node(&forest.nodes[root].root_node, Some(NodeId(root)));
+            dot.root(id);
+        }
+        Ok(dot.render())
+    }
+
+    /// Id in `dot` of `node`, whose id in `forest` is `forest_id`, `None`
+    /// for text leaves, adding its subtree to `dot` the first time.
+    fn dot_node(
+        &self,
+        dot: &mut ForestDot,
+        forest: &Forest<V>,
+        forest_id: Option<NodeId>,
+        node: &Arc<Node<V::Payload>>,
+        input: &str,
+        normalized: &Normalized,
+    ) -> RustlingResult<usize> {
+        let (id, known) = dot.node(node, forest_id);
+        if known {
+            return Ok(id);
+        }
+        let byte_range = normalized.original_range(node.byte_range);
+        let probalog = self
+            .model
+            .classify(&self.extractor.for_node(node), &Truth(true))?;
+        dot.label(
+            id,
+            self.resolve_sym(&node.rule_sym)
+                .unwrap_or("<unknown>")
+                .to_string(),
+        );
+        dot.label(
+            id,
+            format!(
+                "{:?} [{}..{}]",
+                &input[byte_range.0..byte_range.1],
+                byte_range.0,
+                byte_range.1
+            ),
+        );
+        dot.label(id, format!("probalog: {:.3}", probalog));
+        for (index, child) in node.children.iter().enumerate() {
+            let child_forest_id = forest_id.and_then(|f| forest.child_id(f, index));
+            let child_id = self.dot_node(dot, forest, child_forest_id, child, input, normalized)?;
+            dot.edge(id, child_id);
+        }
+        Ok(id)
+    }
+
+    /// Explanation of the tree rooted at `node`, whose id in `forest` is
+    /// `id`, `None` for text leaves.
+    fn explain_node(
+        &self,
+        forest: &Forest<V>,
+        id: Option<NodeId>,
+        node: &Arc<Node<V::Payload>>,
+        input: &str,
+        normalized: &Normalized,
+    ) -> RustlingResult<Explanation> {
+        let byte_range = normalized.original_range(node.byte_range);
+        Ok(Explanation {
+            rule: self
+                .resolve_sym(&node.rule_sym)
+                .unwrap_or("<unknown>")
+                .to_string(),
+            start: byte_range.0,
+            end: byte_range.1,
+            text: input[byte_range.0..byte_range.1].to_string(),
+            value: id.map(|id| format!("{:?}", forest.nodes[id.0].value)),
+            probalog: self
+                .model
+                .classify(&self.extractor.for_node(node), &Truth(true))?,
+            children: node
+                .children
+                .iter()
+                .enumerate()
+                .map(|(index, c)| {
+                    let child_id = id.and_then(|id| forest.child_id(id, index));
+                    self.explain_node(forest, child_id, c, input, normalized)
+                })
+                .collect::<RustlingResult<_>>()?,
+        })
     }
 
     pub fn candidates<Tagger: MaxElementTagger<V>>(
@@ -150,7 +347,18 @@ where
         input: &str,
         tagger: &Tagger,
     ) -> RustlingResult<Vec<Candidate<V, Tagger::O>>> {
-        Ok(tagger.tag(self.raw_candidates(input)?))
+        Ok(self.candidates_with_report(input, tagger)?.0)
+    }
+
+    /// Same as `candidates`, also reporting the parsing limits that were
+    /// reached on this input.
+    pub fn candidates_with_report<Tagger: MaxElementTagger<V>>(
+        &self,
+        input: &str,
+        tagger: &Tagger,
+    ) -> RustlingResult<(Candidates<V, Tagger::O>, ParsingReport)> {
+        let (candidates, report) = self.raw_candidates(input)?;
+        Ok((tagger.tag(candidates), report))
     }
 
     pub fn parse<Tagger: MaxElementTagger<V>>(
@@ -158,11 +366,67 @@ where
         input: &str,
         tagger: &Tagger,
     ) -> RustlingResult<Vec<ParserMatch<Tagger::O>>> {
-        Ok(self
-            .candidates(input, tagger)?
+        Ok(self.parse_with_report(input, tagger)?.0)
+    }
+
+    /// Same as `parse`, also reporting the parsing limits that were reached
+    /// on this input.
+    pub fn parse_with_report<Tagger: MaxElementTagger<V>>(
+        &self,
+        input: &str,
+        tagger: &Tagger,
+    ) -> RustlingResult<(Vec<ParserMatch<Tagger::O>>, ParsingReport)> {
+        let (candidates, report) = self.candidates_with_report(input, tagger)?;
+        let matches = candidates
             .into_iter()
             .filter_map(|c| if c.tagged { Some(c.match_) } else { None })
-            .collect())
+            .collect();
+        Ok((matches, report))
+    }
+
+    /// For each span tagged by `tagger`, the `k` most probable candidates
+    /// of distinct values parsed on this exact span.
+    pub fn n_best<Tagger: MaxElementTagger<V>>(
+        &self,
+        input: &str,
+        tagger: &Tagger,
+        k: usize,
+    ) -> RustlingResult<Vec<SpanAlternatives<V, Tagger::O>>>
+    where
+        Tagger::O: PartialEq,
+    {
+        let mut candidates = self.candidates(input, tagger)?;
+        let mut spans: Vec<SpanAlternatives<V, Tagger::O>> = vec![];
+        for c in candidates.iter().filter(|c| c.tagged) {
+            if spans.iter().all(|s| s.byte_range != c.match_.byte_range) {
+                spans.push(SpanAlternatives {
+                    byte_range: c.match_.byte_range,
+                    alternatives: vec![],
+                });
+            }
+        }
+        candidates.sort_by(|a, b| {
+            b.match_
+                .probalog
+                .partial_cmp(&a.match_.probalog)
+                .unwrap_or(::std::cmp::Ordering::Equal)
+        });
+        for c in candidates {
+            if let Some(span) = spans
+                .iter_mut()
+                .find(|s| s.byte_range == c.match_.byte_range)
+            {
+                if span.alternatives.len() < k
+                    && span
+                        .alternatives
+                        .iter()
+                        .all(|a| a.match_.value != c.match_.value)
+                {
+                    span.alternatives.push(c);
+                }
+            }
+        }
+        Ok(spans)
     }
 
     pub fn analyse<Tagger: MaxElementTagger<V>>(
@@ -292,6 +556,111 @@ mod tests {
         assert_eq!(vec![Int(12), Int(1000), Int(12000)], values);
     }
 
+    #[test]
+    fn test_rule_set_shares_subtrees() {
+        let rule_set = rules();
+        let output_stash = rule_set.apply_all("foobar: 12 thousands").unwrap();
+        let twelve = &output_stash[0].root_node;
+        let thousand = &output_stash[1].root_node;
+        let compo = &output_stash[2].root_node;
+        assert_eq!(Range(8, 20), compo.byte_range);
+        assert_eq!(3, compo.height());
+        assert_eq!(5, compo.num_nodes());
+        assert!(::std::sync::Arc::ptr_eq(twelve, &compo.children[0]));
+        assert!(::std::sync::Arc::ptr_eq(thousand, &compo.children[1]));
+    }
+
+    #[test]
+    fn test_rule_set_parsing_report() {
+        let mut rule_set = rules();
+        let (nodes, report) = rule_set
+            .apply_all_with_report("foobar: 12 thousands")
+            .unwrap();
+        assert_eq!(3, nodes.len());
+        assert_eq!(None, report.stopped_by());
+        assert!(!report.is_truncated());
+        assert_eq!(5, report.duplicates_suppressed);
+
+        rule_set.set_config(ParsingConfig {
+            iterations_max: 1,
+            ..ParsingConfig::default()
+        });
+        let (nodes, report) = rule_set
+            .apply_all_with_report("foobar: 12 thousands")
+            .unwrap();
+        let values: Vec<_> = nodes.iter().map(|pn| pn.value).collect();
+        assert_eq!(vec![Int(12), Int(1000)], values);
+        assert_eq!(Some(ParsingLimit::Iterations), report.stopped_by());
+
+        rule_set.set_config(ParsingConfig {
+            max_tree_height: Some(2),
+            ..ParsingConfig::default()
+        });
+        let (nodes, report) = rule_set
+            .apply_all_with_report("foobar: 12 thousands")
+            .unwrap();
+        assert_eq!(2, nodes.len());
+        assert_eq!(None, report.stopped_by());
+        assert!(report.has_hit(ParsingLimit::TreeHeight));
+
+        rule_set.set_config(ParsingConfig {
+            max_rule_output: Some(1),
+            ..ParsingConfig::default()
+        });
+        let (nodes, report) = rule_set.apply_all_with_report("12 and 42").unwrap();
+        let values: Vec<_> = nodes.iter().map(|pn| pn.value).collect();
+        // the capped rule catches up on the next pass
+        assert_eq!(vec![Int(12), Int(42)], values);
+        assert_eq!(3, report.iterations);
+        assert_eq!(vec![ParsingLimit::RuleOutput], report.limits);
+    }
+
+    #[test]
+    fn test_agenda_strategy_matches_fixed_point() {
+        let fixed_point = rules_with_enum_value();
+        let mut agenda = rules_with_enum_value();
+        agenda.set_config(ParsingConfig {
+            strategy: EvaluationStrategy::Agenda,
+            ..ParsingConfig::default()
+        });
+        for sentence in &["foo: 1.5^2", "2^3^4 and 1.5^2^2", "nothing here", "1 2 3"] {
+            let (expected, expected_report) = fixed_point.apply_all_with_report(sentence).unwrap();
+            let (nodes, report) = agenda.apply_all_with_report(sentence).unwrap();
+            assert_eq!(expected, nodes);
+            assert_eq!(expected_report.iterations, report.iterations);
+            assert_eq!(expected_report.limits, report.limits);
+            assert!(report.rule_applications <= expected_report.rule_applications);
+        }
+        // "int" and "fp" only consume text, they are not applied again
+        let (_, expected_report) = fixed_point.apply_all_with_report("foo: 1.5^2").unwrap();
+        let (_, report) = agenda.apply_all_with_report("foo: 1.5^2").unwrap();
+        assert_eq!(9, expected_report.rule_applications);
+        assert_eq!(5, report.rule_applications);
+    }
+
+    #[test]
+    fn test_predicate_memoization() {
+        let rule_set = rules_with_enum_value();
+        let (nodes, report) = rule_set.apply_all_with_report("foo: 1.5^2^2").unwrap();
+        let values: Vec<_> = nodes.iter().map(|pn| pn.value.clone()).collect();
+        assert_eq!(
+            vec![
+                MyValue::UI(Int(1)),
+                MyValue::UI(Int(5)),
+                MyValue::UI(Int(2)),
+                MyValue::UI(Int(2)),
+                MyValue::FP(F32(1.5)),
+                MyValue::FP(F32(2.25)),
+                MyValue::FP(F32(5.0625)),
+            ],
+            values
+        );
+        // once the ints are known, only the new floats are tested by "pow"
+        assert_eq!(2, report.predicate_memo_hits);
+        assert_eq!(4, report.predicate_memo_misses);
+        assert_eq!(Some(1.0 / 3.0), report.predicate_memo_hit_rate());
+    }
+
     #[test]
     fn test_integer_numeric_infix_rule() {
         let b = RuleSetBuilder::new(
@@ -324,6 +693,235 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sequence_rule() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        b.rule_1_terminal("int", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.rule_seq(
+            "sum of four",
+            seq![
+                dim!(Int),
+                b.reg("\\+").unwrap(),
+                dim!(Int),
+                b.reg("\\+").unwrap(),
+                dim!(Int),
+                b.reg("\\+").unwrap(),
+                dim!(Int),
+            ],
+            |args| {
+                assert_eq!(Some("+"), args[1].as_text().map(|t| t.group(0)));
+                Ok(Int(args
+                    .iter()
+                    .filter_map(|a| a.attempt_value::<Int>())
+                    .map(|i| i.0)
+                    .sum()))
+            },
+        );
+        let rs = b.build();
+        let results = rs.apply_all("foo: 1 + 2 +3 + 4, 5 + 6").unwrap();
+        let values: Vec<_> = results.iter().map(|pn| pn.value).collect();
+        assert_eq!(
+            vec![Int(1), Int(2), Int(3), Int(4), Int(5), Int(6), Int(10)],
+            values
+        );
+        let sum = &results[6];
+        assert_eq!(Range(5, 17), sum.root_node.byte_range);
+        assert_eq!(7, sum.root_node.children.len());
+    }
+
+    #[test]
+    fn test_sequence_rule_with_combinators() {
+        use crate::core::{one_or_more, optional};
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        b.rule_1_terminal("int", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.rule_seq(
+            "sum",
+            seq![
+                optional(b.reg("sum").unwrap()),
+                one_or_more(dim!(Int)),
+                any_of![b.reg("plus").unwrap(), b.reg("minus").unwrap()],
+                dim!(Int),
+            ],
+            |args| {
+                let prefixed = args[0].as_optional().unwrap().get().is_some();
+                let ints: usize = args[1]
+                    .as_repeated()
+                    .unwrap()
+                    .all()
+                    .iter()
+                    .filter_map(|a| a.attempt_value::<Int>())
+                    .map(|i| i.0)
+                    .sum();
+                let last = args[3].attempt_value::<Int>().unwrap().0;
+                let total = match args[2].as_any_of().unwrap().branch() {
+                    0 => ints + last,
+                    _ => ints - last,
+                };
+                Ok(Int(if prefixed { total * 100 } else { total }))
+            },
+        );
+        let rs = b.build();
+        let results = rs.apply_all("sum 4 5 minus 2").unwrap();
+        let sums: Vec<_> = results
+            .iter()
+            .filter(|pn| pn.root_node.children.len() > 1)
+            .map(|pn| (pn.value, pn.root_node.byte_range))
+            .collect();
+        assert_eq!(
+            vec![
+                (Int(700), Range(0, 15)),
+                (Int(7), Range(4, 15)),
+                (Int(3), Range(6, 15)),
+            ],
+            sums
+        );
+    }
+
+    #[test]
+    fn test_any_of_pattern() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        b.rule_1_terminal("int", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.rule_2(
+            "times",
+            any_of![dim!(Int), b.reg("an?").unwrap()],
+            b.reg("times").unwrap(),
+            |n, _| match n.branch() {
+                0 => Ok(Int(n.arg().attempt_value::<Int>().unwrap().0 * 10)),
+                _ => {
+                    assert_eq!(Some("a"), n.arg().as_text().map(|t| t.group(0)));
+                    Ok(Int(10))
+                }
+            },
+        );
+        let rs = b.build();
+        let values: Vec<_> = rs
+            .apply_all("3 times and a times")
+            .unwrap()
+            .into_iter()
+            .map(|pn| pn.value)
+            .collect();
+        assert_eq!(vec![Int(3), Int(30), Int(10)], values);
+    }
+
+    #[test]
+    fn test_gazetteer_rule() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        let months = ["january", "february", "march", "april"];
+        b.rule_1_terminal("month", b.gazetteer("months", months).unwrap(), |m| {
+            Ok(Int(m.entry().unwrap() + 1))
+        });
+        let rs = b.build();
+        let values: Vec<_> = rs
+            .apply_all("from March to April, marching")
+            .unwrap()
+            .into_iter()
+            .map(|pn| pn.value)
+            .collect();
+        assert_eq!(vec![Int(3), Int(4)], values);
+    }
+
+    #[test]
+    fn test_lookaround_rules() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        b.rule_1_terminal("int", b.reg_neg_lb("\\d+", "[a-z]").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.rule_1_terminal("time", b.reg_pos_lb("\\d+", "at\\s+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))? * 100))
+        });
+        b.rule_1_terminal("amount", b.reg_pos_lh("\\d+", "\\s*\\$").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))? * 1000))
+        });
+        let rs = b.build();
+        let parsed = rs.apply_all("v5 at 7, 3 $").unwrap();
+        assert!(parsed.iter().all(|pn| pn.root_node.byte_range.len() == 1));
+        let values: Vec<_> = parsed.into_iter().map(|pn| pn.value).collect();
+        assert_eq!(vec![Int(7), Int(3), Int(700), Int(3000)], values);
+    }
+
+    #[test]
+    fn test_fuzzy_rule() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        let days = ["monday", "tuesday", "wednesday"];
+        b.rule_1_terminal("day", b.fuzzy("days", days, 2), |d| {
+            Ok(Int(d.entry().unwrap() * 10 + d.edit_distance().unwrap()))
+        });
+        let rs = b.build();
+        let parsed = rs.apply_all("see you wensday or Tusday").unwrap();
+        assert_eq!(Some(2), parsed[0].root_node.children[0].edit_distance());
+        let values: Vec<_> = parsed.into_iter().map(|pn| pn.value).collect();
+        assert_eq!(vec![Int(22), Int(11)], values);
+    }
+
+    #[test]
+    fn test_adjacency_policy() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        b.rule_1_terminal("int", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.with_adjacency(AdjacencyPolicy::whitespace().with_fillers(&[',']), |b| {
+            b.rule_2(
+                "thousands",
+                dim!(Int),
+                dim!(Int, vec![Box::new(|a: &Int| a.0 < 1000)]),
+                |a, b| Ok(Int(a.value().0 * 1000 + b.value().0)),
+            );
+        });
+        b.rule_3(
+            "range",
+            dim!(Int),
+            b.reg("-").unwrap(),
+            dim!(Int),
+            |a, _, b| Ok(Int(b.value().0 - a.value().0)),
+        );
+        let rs = b.build();
+        let values: Vec<_> = rs
+            .apply_all("12, 500 to 12 ,400")
+            .unwrap()
+            .into_iter()
+            .map(|pn| pn.value)
+            .collect();
+        assert_eq!(
+            vec![Int(12), Int(500), Int(12), Int(400), Int(12500), Int(12400)],
+            values
+        );
+        // the "range" rule was added with the default policy
+        let values: Vec<_> = rs
+            .apply_all("1, - 2")
+            .unwrap()
+            .into_iter()
+            .map(|pn| pn.value)
+            .collect();
+        assert_eq!(vec![Int(1), Int(2)], values);
+    }
+
     rustling_value! {
         #[doc="an union"]
         #[derive(Clone,PartialEq,Debug)]
@@ -412,6 +1010,7 @@ mod tests {
                 classifiers: FnvHashMap::default(),
             },
             extractor: TestFeatExtractor(),
+            normalizer: None,
         }
     }
 
@@ -473,4 +1072,278 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn test_parser_with_normalizer() {
+        let mut parser = parser();
+        parser.set_normalizer(
+            NormalizerChain::default()
+                .then(Nfkc)
+                .then(WhitespaceCollapsing),
+        );
+        let input = "Ｆoo:  １.5 ^\t2";
+        let matches = parser.parse(input, &TestMaxElementTagger).unwrap();
+        assert_eq!(1, matches.len());
+        assert_eq!(MyValue::FP(F32(2.25)), matches[0].value);
+        assert_eq!(Range(8, 17), matches[0].byte_range);
+        assert_eq!(Range(6, 13), matches[0].char_range);
+        assert_eq!(Range(6, 13), matches[0].utf16_range);
+        assert_eq!("１.5 ^\t2", &input[8..17]);
+    }
+
+    fn tagged_values<T: MaxElementTagger<MyValue, O = MyValue>>(
+        tagger: &T,
+        candidates: RawCandidates<MyValue>,
+    ) -> Vec<MyValue> {
+        tagger
+            .tag(candidates)
+            .into_iter()
+            .filter(|c| c.tagged)
+            .map(|c| c.match_.value)
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_taggers() {
+        let parser = parser();
+        // 1(5..6) 5(7..8) 2(9..10) 1.5(5..8) 2.25(5..10)
+        let (mut candidates, _) = parser.raw_candidates("foo: 1.5^2").unwrap();
+        for (_, m) in candidates.iter_mut() {
+            m.probalog = match m.value {
+                MyValue::FP(F32(v)) if v > 2.0 => 0.1f32.ln(),
+                MyValue::UI(Int(1)) => {
+                    m.latent = true;
+                    0.0
+                }
+                _ => 0.0,
+            };
+        }
+
+        let longest = tagged_values(&LongestSpanTagger::default(), candidates.clone());
+        assert_eq!(vec![MyValue::FP(F32(2.25))], longest);
+
+        let weighted = tagged_values(&WeightedSpanTagger::default(), candidates.clone());
+        assert_eq!(vec![MyValue::FP(F32(1.5)), MyValue::UI(Int(2))], weighted);
+
+        let all = tagged_values(&KeepAllTagger::default(), candidates.clone());
+        assert_eq!(
+            vec![
+                MyValue::FP(F32(2.25)),
+                MyValue::FP(F32(1.5)),
+                MyValue::UI(Int(5)),
+                MyValue::UI(Int(2))
+            ],
+            all
+        );
+        let all = tagged_values(&KeepAllTagger::new(LatentPolicy::Include), candidates);
+        assert_eq!(5, all.len());
+        assert_eq!(MyValue::UI(Int(1)), all[2]);
+    }
+
+    #[test]
+    fn test_segmentation_tagger() {
+        let parser = parser();
+        // 1(5..6) 5(7..8) 2(9..10) 1.5(5..8) 2.25(5..10)
+        let (mut candidates, _) = parser.raw_candidates("foo: 1.5^2").unwrap();
+        for (_, m) in candidates.iter_mut() {
+            m.probalog = match m.value {
+                MyValue::FP(F32(v)) if v > 2.0 => -2.0,
+                MyValue::UI(Int(1)) | MyValue::UI(Int(5)) => -0.7,
+                _ => -0.1,
+            };
+        }
+
+        let tagger = SegmentationTagger::default();
+        let segmentation = tagger.segment(candidates.clone());
+        assert_eq!(5, segmentation.score.coverage);
+        assert_eq!(-2.0, segmentation.score.probalog);
+        assert_eq!(
+            vec![MyValue::FP(F32(2.25))],
+            tagged_values(&tagger, candidates.clone())
+        );
+
+        let tagger = SegmentationTagger::new(
+            SegmentationObjective::TotalProbalog { threshold: -1.0 },
+            LatentPolicy::Exclude,
+        );
+        let segmentation = tagger.segment(candidates.clone());
+        assert_eq!(4, segmentation.score.coverage);
+        assert_eq!(2, segmentation.score.num_matches);
+        assert_eq!(
+            vec![MyValue::FP(F32(1.5)), MyValue::UI(Int(2))],
+            tagged_values(&tagger, candidates)
+        );
+    }
+
+    #[test]
+    fn test_n_best() {
+        let b = RuleSetBuilder::new(
+            BoundariesChecker::detailed(),
+            BoundariesChecker::separated_alphanumeric_word(),
+        );
+        b.rule_1("int", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.rule_1("dozens", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))? * 12))
+        });
+        b.rule_1("int again", b.reg("\\d+").unwrap(), |a| {
+            Ok(Int(usize::from_str(a.group(0))?))
+        });
+        b.rule_1("fp", b.reg("\\d+\\.\\d+").unwrap(), |a| {
+            Ok(F32(f32::from_str(a.group(0))?))
+        });
+        let parser = Parser::new(
+            b.build(),
+            Model {
+                classifiers: FnvHashMap::default(),
+            },
+            TestFeatExtractor(),
+        );
+        let values = |k| -> Vec<Vec<MyValue>> {
+            parser
+                .n_best("3 or 1.5", &KeepAllTagger::default(), k)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.alternatives.into_iter().map(|c| c.match_.value).collect())
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                vec![MyValue::UI(Int(3)), MyValue::UI(Int(36))],
+                vec![MyValue::FP(F32(1.5))],
+                vec![MyValue::UI(Int(1)), MyValue::UI(Int(12))],
+                vec![MyValue::UI(Int(5)), MyValue::UI(Int(60))],
+            ],
+            values(3)
+        );
+        assert_eq!(vec![MyValue::UI(Int(3))], values(1)[0]);
+    }
+
+    #[test]
+    fn test_explain() {
+        let explanations = parser().explain("foo: 1.5^2").unwrap();
+        assert_eq!(5, explanations.len());
+        let pow = explanations.iter().find(|e| e.rule == "pow").unwrap();
+        assert_eq!(
+            "pow \"1.5^2\" [5..10] = FP(F32(2.25)) (probalog: 0.000)\n\
+             \x20 fp \"1.5\" [5..8] = FP(F32(1.5)) (probalog: 0.000)\n\
+             \x20   \\d+\\.\\d+ \"1.5\" [5..8] (probalog: 0.000)\n\
+             \x20 \\^ \"^\" [8..9] (probalog: 0.000)\n\
+             \x20 int \"2\" [9..10] = UI(Int(2)) (probalog: 0.000)\n\
+             \x20   \\d+ \"2\" [9..10] (probalog: 0.000)\n",
+            pow.to_text()
+        );
+        let json: ::serde_json::Value = ::serde_json::from_str(&pow.to_json()).unwrap();
+        assert_eq!("pow", json["rule"]);
+        assert_eq!("FP(F32(2.25))", json["value"]);
+        assert_eq!("2", json["children"][2]["text"]);
+        assert_eq!(9, json["children"][2]["start"]);
+        assert_eq!("UI(Int(2))", json["children"][2]["value"]);
+        assert!(json["children"][1]["value"].is_null());
+    }
+
+    #[test]
+    fn test_forest_dot() {
+        let dot = parser().forest_dot("foo: 1.5^2").unwrap();
+        // 5 values and 5 text leaves, "2" being shared by its value and "pow"
+        assert_eq!(10, dot.matches(" [label=").count());
+        assert_eq!(5, dot.matches("peripheries=2").count());
+        assert_eq!(7, dot.matches(" -> ").count());
+        assert!(dot.starts_with("digraph forest {\n"));
+        assert!(dot.contains(
+            "[label=\"pow\\n\\\"1.5^2\\\" [5..10]\\nprobalog: 0.000\\n= FP(F32(2.25))\", \
+             peripheries=2];"
+        ));
+        assert!(dot.contains("[label=\"\\\\^\\n\\\"^\\\" [8..9]\\nprobalog: 0.000\"];"));
+
+        // "2x" is not a word: neither "2" nor "pow" are parsed values, but
+        // both are still in the forest
+        let dot = parser().forest_dot("foo: 1.5^2x").unwrap();
+        assert_eq!(10, dot.matches(" [label=").count());
+        assert_eq!(3, dot.matches("peripheries=2").count());
+        assert!(
+            dot.contains("[label=\"int\\n\\\"2\\\" [9..10]\\nprobalog: 0.000\\n= UI(Int(2))\"];")
+        );
+    }
+
+    struct WhitespaceTokenizer;
+
+    impl Tokenizer for WhitespaceTokenizer {
+        fn tokenize(&self, sentence: &str) -> Vec<Range> {
+            sentence
+                .split_whitespace()
+                .map(|t| {
+                    let start = t.as_ptr() as usize - sentence.as_ptr() as usize;
+                    Range(start, start + t.len())
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_parser_with_tokenizer() {
+        let mut parser = parser();
+        let candidates = parser.candidates("x 1.5 2", &TestMaxElementTagger).unwrap();
+        assert_eq!(4, candidates.len());
+        assert!(candidates.iter().all(|c| c.match_.token_range.is_none()));
+
+        parser.set_tokenizer(WhitespaceTokenizer);
+        let matches: Vec<_> = parser
+            .candidates("x 1.5 2", &TestMaxElementTagger)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.match_.value, c.match_.byte_range, c.match_.token_range))
+            .collect();
+        assert_eq!(
+            vec![
+                (MyValue::FP(F32(1.5)), Range(2, 5), Some(Range(1, 2))),
+                (MyValue::UI(Int(2)), Range(6, 7), Some(Range(2, 3))),
+            ],
+            matches
+        );
+
+        // the token ranges index the tokens of the original input
+        parser.set_normalizer(WhitespaceCollapsing);
+        let matches: Vec<_> = parser
+            .candidates("x  1.5 \t 2", &TestMaxElementTagger)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.match_.byte_range, c.match_.token_range))
+            .collect();
+        assert_eq!(
+            vec![
+                (Range(3, 6), Some(Range(1, 2))),
+                (Range(9, 10), Some(Range(2, 3)))
+            ],
+            matches
+        );
+    }
+
+    #[test]
+    fn test_parser_is_shareable_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        fn assert_send<T: Send>() {}
+        assert_send_sync::<RuleSet<MyValue>>();
+        assert_send_sync::<Parser<MyValue, TestFeat, TestFeatExtractor>>();
+        assert_send::<ParsedNode<MyValue>>();
+        assert_send::<Candidate<MyValue, MyValue>>();
+
+        let parser = ::std::sync::Arc::new(parser());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let parser = parser.clone();
+                ::std::thread::spawn(move || {
+                    parser
+                        .candidates("foo: 1.5^2", &TestMaxElementTagger)
+                        .unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            let candidates = handle.join().unwrap();
+            assert_eq!(5, candidates.len());
+            assert_eq!(MyValue::FP(F32(2.25)), candidates[0].match_.value);
+        }
+    }
 }
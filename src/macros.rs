@@ -129,3 +129,13 @@ macro_rules! dim {
     ($typ:ty) => ( $crate::core::AnyNodePattern::<$typ>::new() );
     ($typ:ty, $predicates:expr) => ( $crate::core::FilterNodePattern::<$typ>::filter($predicates) );
 }
+
+#[macro_export]
+macro_rules! seq {
+    ($($pattern:expr),+ $(,)*) => ( vec![$( $crate::core::IntoAnyPattern::into_any_pattern($pattern) ),+] );
+}
+
+#[macro_export]
+macro_rules! any_of {
+    ($($pattern:expr),+ $(,)*) => ( $crate::core::AnyOf::new($crate::seq![$( $pattern ),+]) );
+}
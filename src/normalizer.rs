@@ -0,0 +1,246 @@
+use crate::Range;
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::UnicodeNormalization;
+
+/// Text rewritten by a `Normalizer`, remembering for each of its bytes the
+/// range of the original text it was produced from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Normalized {
+    pub text: String,
+    spans: Vec<Range>,
+    original_len: usize,
+}
+
+impl Normalized {
+    /// Identity normalization of `input`.
+    pub fn identity(input: &str) -> Normalized {
+        Normalized::from_chunks(
+            input,
+            input
+                .char_indices()
+                .map(|(ix, c)| (Range(ix, ix + c.len_utf8()), c.to_string())),
+        )
+    }
+
+    /// Builds a normalization from the rewriting of consecutive chunks of
+    /// `input`, given as their byte range and replacement.
+    pub fn from_chunks<I>(input: &str, chunks: I) -> Normalized
+    where
+        I: IntoIterator<Item = (Range, String)>,
+    {
+        let mut text = String::with_capacity(input.len());
+        let mut spans = Vec::with_capacity(input.len());
+        for (range, replacement) in chunks {
+            text.push_str(&replacement);
+            spans.extend((0..replacement.len()).map(|_| range));
+        }
+        Normalized {
+            text,
+            spans,
+            original_len: input.len(),
+        }
+    }
+
+    /// Maps a byte range of the normalized text back to the smallest byte
+    /// range of the original text covering what it was produced from.
+    pub fn original_range(&self, range: Range) -> Range {
+        if range.0 < range.1 {
+            Range(self.spans[range.0].0, self.spans[range.1 - 1].1)
+        } else {
+            let position = self
+                .spans
+                .get(range.0)
+                .map(|s| s.0)
+                .unwrap_or(self.original_len);
+            Range(position, position)
+        }
+    }
+
+    /// Applies `normalizer` on the normalized text, keeping the mapping to
+    /// the original text.
+    pub fn then<N: Normalizer + ?Sized>(self, normalizer: &N) -> Normalized {
+        let next = normalizer.normalize(&self.text);
+        let spans = next.spans.iter().map(|s| self.original_range(*s)).collect();
+        Normalized {
+            text: next.text,
+            spans,
+            original_len: self.original_len,
+        }
+    }
+}
+
+/// Rewriting of the input applied before parsing.
+pub trait Normalizer {
+    fn normalize(&self, input: &str) -> Normalized;
+}
+
+/// Lowercases the input, as `str::to_lowercase` does.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CaseFolding;
+
+impl Normalizer for CaseFolding {
+    fn normalize(&self, input: &str) -> Normalized {
+        // `str::to_lowercase` lowercases each char on its own, except for
+        // the final sigma which still gives a single char
+        let lowercase = input.to_lowercase();
+        let mut lowercase_chars = lowercase.chars();
+        Normalized::from_chunks(
+            input,
+            input.char_indices().map(|(ix, c)| {
+                let chunk = lowercase_chars
+                    .by_ref()
+                    .take(c.to_lowercase().count())
+                    .collect();
+                (Range(ix, ix + c.len_utf8()), chunk)
+            }),
+        )
+    }
+}
+
+/// Removes the combining marks of the canonical decomposition of the input,
+/// turning "é" into "e".
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AccentStripping;
+
+impl Normalizer for AccentStripping {
+    fn normalize(&self, input: &str) -> Normalized {
+        Normalized::from_chunks(
+            input,
+            input.char_indices().map(|(ix, c)| {
+                let stripped = ::std::iter::once(c)
+                    .nfd()
+                    .filter(|d| canonical_combining_class(*d) == 0)
+                    .collect();
+                (Range(ix, ix + c.len_utf8()), stripped)
+            }),
+        )
+    }
+}
+
+/// Unicode compatibility normalization (NFKC) of the input, applied on
+/// each starter char together with the combining chars following it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Nfkc;
+
+impl Normalizer for Nfkc {
+    fn normalize(&self, input: &str) -> Normalized {
+        Normalized::from_chunks(
+            input,
+            chunks(input, |_, c| canonical_combining_class(c) != 0)
+                .map(|r| (r, input[r.0..r.1].nfkc().collect())),
+        )
+    }
+}
+
+/// Replaces each run of whitespace of the input by a single space.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WhitespaceCollapsing;
+
+impl Normalizer for WhitespaceCollapsing {
+    fn normalize(&self, input: &str) -> Normalized {
+        Normalized::from_chunks(
+            input,
+            chunks(input, |previous, c| {
+                previous.is_whitespace() && c.is_whitespace()
+            })
+            .map(|r| {
+                let chunk = &input[r.0..r.1];
+                if chunk.starts_with(char::is_whitespace) {
+                    (r, " ".to_string())
+                } else {
+                    (r, chunk.to_string())
+                }
+            }),
+        )
+    }
+}
+
+/// Normalizers applied one after the other.
+#[derive(Default)]
+pub struct NormalizerChain(pub Vec<Box<dyn Normalizer + Send + Sync>>);
+
+impl NormalizerChain {
+    pub fn then<N: Normalizer + Send + Sync + 'static>(mut self, normalizer: N) -> Self {
+        self.0.push(Box::new(normalizer));
+        self
+    }
+}
+
+impl Normalizer for NormalizerChain {
+    fn normalize(&self, input: &str) -> Normalized {
+        self.0
+            .iter()
+            .fold(Normalized::identity(input), |n, normalizer| {
+                n.then(normalizer.as_ref())
+            })
+    }
+}
+
+/// Splits `input` in chunks of chars, a char joining the chunk of the
+/// previous one when `joins(previous, char)` holds.
+fn chunks<'a, F>(input: &'a str, joins: F) -> impl Iterator<Item = Range> + 'a
+where
+    F: Fn(char, char) -> bool + 'a,
+{
+    let mut chars = input.char_indices().peekable();
+    ::std::iter::from_fn(move || {
+        let (start, mut previous) = chars.next()?;
+        let mut end = start + previous.len_utf8();
+        while let Some(&(ix, c)) = chars.peek() {
+            if !joins(previous, c) {
+                break;
+            }
+            chars.next();
+            previous = c;
+            end = ix + c.len_utf8();
+        }
+        Some(Range(start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizers() {
+        let n = CaseFolding.normalize("İSTANBUL");
+        assert_eq!("i̇stanbul", n.text);
+        assert_eq!(Range(0, 2), n.original_range(Range(0, 3)));
+        assert_eq!(Range(2, 9), n.original_range(Range(3, 10)));
+        let n = CaseFolding.normalize("ΟΔΟΣ ΣΑΣ");
+        assert_eq!("ΟΔΟΣ ΣΑΣ".to_lowercase(), n.text);
+        assert_eq!("οδος σας", n.text);
+        assert_eq!(Range(6, 8), n.original_range(Range(6, 8)));
+
+        let n = AccentStripping.normalize("Crème brûlée");
+        assert_eq!("Creme brulee", n.text);
+        assert_eq!(Range(7, 15), n.original_range(Range(6, 12)));
+
+        let n = Nfkc.normalize("ﬁve e\u{301}tages ①");
+        assert_eq!("five étages 1", n.text);
+        assert_eq!(Range(0, 3), n.original_range(Range(0, 2)));
+        assert_eq!(Range(6, 14), n.original_range(Range(5, 12)));
+        assert_eq!(Range(15, 18), n.original_range(Range(13, 14)));
+
+        let n = WhitespaceCollapsing.normalize("a \t\n b ");
+        assert_eq!("a b ", n.text);
+        assert_eq!(Range(1, 5), n.original_range(Range(1, 2)));
+        assert_eq!(Range(7, 7), n.original_range(Range(4, 4)));
+    }
+
+    #[test]
+    fn test_normalizer_chain() {
+        let chain = NormalizerChain::default()
+            .then(Nfkc)
+            .then(CaseFolding)
+            .then(AccentStripping)
+            .then(WhitespaceCollapsing);
+        let input = "Ｔhe  Été";
+        let n = chain.normalize(input);
+        assert_eq!("the ete", n.text);
+        assert_eq!(Range(7, 12), n.original_range(Range(4, 7)));
+        assert_eq!("Été", &input[7..12]);
+        assert_eq!(Range(3, 7), n.original_range(Range(1, 4)));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{Candidate, MaxElementTagger, ParsedNode, ParserMatch, Range, Value};
+
+/// How taggers handle the latent matches, which are only meaningful as
+/// part of a larger match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatentPolicy {
+    /// Latent matches are never tagged.
+    #[default]
+    Exclude,
+    /// Latent matches compete with the other ones.
+    Include,
+}
+
+impl LatentPolicy {
+    fn allows<V>(&self, match_: &ParserMatch<V>) -> bool {
+        match self {
+            LatentPolicy::Exclude => !match_.latent,
+            LatentPolicy::Include => true,
+        }
+    }
+}
+
+/// Greedily tags the longest matches not overlapping an already tagged
+/// one, the most probable first among matches of the same length.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LongestSpanTagger {
+    pub latent: LatentPolicy,
+}
+
+impl LongestSpanTagger {
+    pub fn new(latent: LatentPolicy) -> LongestSpanTagger {
+        LongestSpanTagger { latent }
+    }
+}
+
+impl<V: Value> MaxElementTagger<V> for LongestSpanTagger {
+    type O = V;
+    fn tag(&self, candidates: Vec<(ParsedNode<V>, ParserMatch<V>)>) -> Vec<Candidate<V, V>> {
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&candidates[a].1, &candidates[b].1);
+            b.byte_range
+                .len()
+                .cmp(&a.byte_range.len())
+                .then(
+                    b.probalog
+                        .partial_cmp(&a.probalog)
+                        .unwrap_or(::std::cmp::Ordering::Equal),
+                )
+                .then(a.byte_range.0.cmp(&b.byte_range.0))
+        });
+        let mut tagged = vec![false; candidates.len()];
+        let mut selected: Vec<Range> = vec![];
+        for ix in order {
+            let match_ = &candidates[ix].1;
+            if self.latent.allows(match_)
+                && selected.iter().all(|r| r.is_disjoint(&match_.byte_range))
+            {
+                selected.push(match_.byte_range);
+                tagged[ix] = true;
+            }
+        }
+        into_candidates(candidates, &tagged)
+    }
+}
+
+/// Tags the non-overlapping matches maximizing the sum of their byte length
+/// weighted by their probability, `len * exp(probalog)`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WeightedSpanTagger {
+    pub latent: LatentPolicy,
+}
+
+impl WeightedSpanTagger {
+    pub fn new(latent: LatentPolicy) -> WeightedSpanTagger {
+        WeightedSpanTagger { latent }
+    }
+}
+
+impl<V: Value> MaxElementTagger<V> for WeightedSpanTagger {
+    type O = V;
+    fn tag(&self, candidates: Vec<(ParsedNode<V>, ParserMatch<V>)>) -> Vec<Candidate<V, V>> {
+        let eligible: Vec<usize> = (0..candidates.len())
+            .filter(|&ix| self.latent.allows(&candidates[ix].1))
+            .collect();
+        let ranges: Vec<Range> = eligible
+            .iter()
+            .map(|&ix| candidates[ix].1.byte_range)
+            .collect();
+        let chosen = max_weight_disjoint(
+            &ranges,
+            0.0f32,
+            |total, i| {
+                let match_ = &candidates[eligible[i]].1;
+                total + match_.byte_range.len() as f32 * match_.probalog.exp()
+            },
+            |a, b| a > b,
+        );
+        let mut tagged = vec![false; candidates.len()];
+        for i in chosen {
+            tagged[eligible[i]] = true;
+        }
+        into_candidates(candidates, &tagged)
+    }
+}
+
+/// What a `SegmentationTagger` maximizes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SegmentationObjective {
+    /// The number of covered bytes, then the total `probalog`.
+    #[default]
+    CoverageThenProbalog,
+    /// The total of `probalog - threshold`, so that only the matches more
+    /// probable than `threshold` may improve a segmentation.
+    TotalProbalog { threshold: f32 },
+}
+
+impl SegmentationObjective {
+    pub fn is_better(&self, a: &SegmentationScore, b: &SegmentationScore) -> bool {
+        match *self {
+            SegmentationObjective::CoverageThenProbalog => {
+                a.coverage > b.coverage || (a.coverage == b.coverage && a.probalog > b.probalog)
+            }
+            SegmentationObjective::TotalProbalog { threshold } => {
+                a.probalog - threshold * a.num_matches as f32
+                    > b.probalog - threshold * b.num_matches as f32
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SegmentationScore {
+    /// Number of bytes covered by the matches
+    pub coverage: usize,
+    /// Sum of the `probalog` of the matches
+    pub probalog: f32,
+    pub num_matches: usize,
+}
+
+impl SegmentationScore {
+    fn with<V>(&self, match_: &ParserMatch<V>) -> SegmentationScore {
+        SegmentationScore {
+            coverage: self.coverage + match_.byte_range.len(),
+            probalog: self.probalog + match_.probalog,
+            num_matches: self.num_matches + 1,
+        }
+    }
+}
+
+/// Non-overlapping matches chosen by a `SegmentationTagger`, tagged among
+/// all the candidates.
+#[derive(Debug, Clone)]
+pub struct Segmentation<V: Value> {
+    pub candidates: Vec<Candidate<V, V>>,
+    pub score: SegmentationScore,
+}
+
+/// Tags the set of non-overlapping matches that is optimal for its
+/// objective, where greedy taggers only make locally optimal choices.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SegmentationTagger {
+    pub objective: SegmentationObjective,
+    pub latent: LatentPolicy,
+}
+
+impl SegmentationTagger {
+    pub fn new(objective: SegmentationObjective, latent: LatentPolicy) -> SegmentationTagger {
+        SegmentationTagger { objective, latent }
+    }
+
+    pub fn segment<V: Value>(
+        &self,
+        candidates: Vec<(ParsedNode<V>, ParserMatch<V>)>,
+    ) -> Segmentation<V> {
+        let eligible: Vec<usize> = (0..candidates.len())
+            .filter(|&ix| self.latent.allows(&candidates[ix].1))
+            .collect();
+        let ranges: Vec<Range> = eligible
+            .iter()
+            .map(|&ix| candidates[ix].1.byte_range)
+            .collect();
+        let chosen = max_weight_disjoint(
+            &ranges,
+            SegmentationScore::default(),
+            |score, i| score.with(&candidates[eligible[i]].1),
+            |a, b| self.objective.is_better(a, b),
+        );
+        let mut tagged = vec![false; candidates.len()];
+        let mut score = SegmentationScore::default();
+        for i in chosen {
+            tagged[eligible[i]] = true;
+            score = score.with(&candidates[eligible[i]].1);
+        }
+        Segmentation {
+            candidates: into_candidates(candidates, &tagged),
+            score,
+        }
+    }
+}
+
+impl<V: Value> MaxElementTagger<V> for SegmentationTagger {
+    type O = V;
+    fn tag(&self, candidates: Vec<(ParsedNode<V>, ParserMatch<V>)>) -> Vec<Candidate<V, V>> {
+        self.segment(candidates).candidates
+    }
+}
+
+/// Tags all the matches.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct KeepAllTagger {
+    pub latent: LatentPolicy,
+}
+
+impl KeepAllTagger {
+    pub fn new(latent: LatentPolicy) -> KeepAllTagger {
+        KeepAllTagger { latent }
+    }
+}
+
+impl<V: Value> MaxElementTagger<V> for KeepAllTagger {
+    type O = V;
+    fn tag(&self, candidates: Vec<(ParsedNode<V>, ParserMatch<V>)>) -> Vec<Candidate<V, V>> {
+        let tagged: Vec<bool> = candidates
+            .iter()
+            .map(|c| self.latent.allows(&c.1))
+            .collect();
+        into_candidates(candidates, &tagged)
+    }
+}
+
+/// Indices of the non-empty, pairwise disjoint `ranges` whose total is best,
+/// the total of a set being built from `zero` by `add` and compared with
+/// `better`.
+fn max_weight_disjoint<W, A, B>(ranges: &[Range], zero: W, add: A, better: B) -> Vec<usize>
+where
+    W: Clone,
+    A: Fn(&W, usize) -> W,
+    B: Fn(&W, &W) -> bool,
+{
+    let mut by_end: Vec<usize> = (0..ranges.len()).filter(|&i| ranges[i].len() > 0).collect();
+    by_end.sort_by_key(|&i| (ranges[i].1, ranges[i].0));
+    // as the ranges are sorted by end, the ones before position `k` which are
+    // disjoint from `i` form a prefix, whose length is returned
+    let compatible =
+        |k: usize, i: usize| by_end[..k].partition_point(|&j| ranges[j].1 <= ranges[i].0);
+    // best[k]: best total among the first k ranges by end, with the position
+    // of the last range it takes
+    let mut best: Vec<(W, Option<usize>)> = vec![(zero, None)];
+    for (k, &i) in by_end.iter().enumerate() {
+        let with = add(&best[compatible(k, i)].0, i);
+        if better(&with, &best[k].0) {
+            best.push((with, Some(k)));
+        } else {
+            let without = best[k].clone();
+            best.push(without);
+        }
+    }
+    let mut chosen = vec![];
+    let mut k = by_end.len();
+    while let Some(taken) = best[k].1 {
+        let i = by_end[taken];
+        chosen.push(i);
+        k = compatible(taken, i);
+    }
+    chosen
+}
+
+/// Candidates sorted by position, then by decreasing length.
+fn into_candidates<V: Value>(
+    candidates: Vec<(ParsedNode<V>, ParserMatch<V>)>,
+    tagged: &[bool],
+) -> Vec<Candidate<V, V>> {
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .zip(tagged.iter())
+        .map(|((node, match_), tagged)| Candidate {
+            node,
+            match_,
+            tagged: *tagged,
+        })
+        .collect();
+    candidates.sort_by_key(|c| {
+        (
+            c.match_.byte_range.0,
+            ::std::cmp::Reverse(c.match_.byte_range.1),
+        )
+    });
+    candidates
+}
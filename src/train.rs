@@ -1,6 +1,6 @@
 use crate::{
-    Classifier, Feature, FeatureExtractor, Model, Node, ParsedNode, Range, RuleId, RuleSet,
-    RustlingResult, StashIndexable, Truth, Value,
+    CaseFolding, Classifier, Feature, FeatureExtractor, Model, Node, Normalizer, ParsedNode, Range,
+    RuleId, RuleSet, RustlingResult, StashIndexable, Truth, Value,
 };
 use fnv::FnvHashMap;
 use fnv::FnvHashSet;
@@ -24,6 +24,7 @@ pub trait Check<V: Value>: Debug {
     fn check(&self, value: &ParsedNode<V>) -> bool;
 }
 
+/// Trains a model on `examples`, lowercased before parsing.
 pub fn train<V, F, E>(
     rules: &RuleSet<V>,
     examples: Vec<Example<V>>,
@@ -34,17 +35,38 @@ where
     V::Payload: Debug + Eq + Hash,
     F: Feature,
     E: FeatureExtractor<V, F>,
+{
+    train_with_normalizer(rules, examples, feature_extractor, &CaseFolding)
+}
+
+/// Trains a model on `examples`, normalized with `normalizer` before
+/// parsing.
+pub fn train_with_normalizer<V, F, E, N>(
+    rules: &RuleSet<V>,
+    examples: Vec<Example<V>>,
+    feature_extractor: E,
+    normalizer: &N,
+) -> RustlingResult<Model<RuleId, Truth, F>>
+where
+    V: Value + Debug + StashIndexable,
+    V::Payload: Debug + Eq + Hash,
+    F: Feature,
+    E: FeatureExtractor<V, F>,
+    N: Normalizer + ?Sized,
 {
     let mut classified_ex: FnvHashMap<RuleId, Vec<(FnvHashMap<F, usize>, Truth)>> =
         FnvHashMap::default();
     for ex in examples.iter() {
-        let stash = rules.apply_all(&ex.text.to_lowercase()).unwrap();
+        let normalized = normalizer.normalize(ex.text);
+        let stash = rules.apply_all(&normalized.text).unwrap();
 
-        // - keep only full-range parsed nodes
+        // - keep only parsed nodes covering the whole original example
         // - partition them according to the example check value
         let (positive_parsed_nodes, negative_parse_nodes) = stash
             .into_iter()
-            .filter(|candidate| candidate.root_node.byte_range == Range(0, ex.text.len()))
+            .filter(|candidate| {
+                normalized.original_range(candidate.root_node.byte_range) == Range(0, ex.text.len())
+            })
             .partition::<Vec<_>, _>(|candidate| ex.predicate.check(&candidate));
         // - example sanity check
         if positive_parsed_nodes.is_empty() {